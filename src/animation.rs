@@ -0,0 +1,396 @@
+//! Sprites and frame based animations for matrix displays
+//!
+//! A [`Sprite`] is a monochrome bitmap with an optional transparency mask.
+//! An [`Animation`] is a sequence of sprites each shown for its own duration,
+//! played back by a [`Player`] onto a [`FrameBuffer`] either asynchronously
+//! with `play` or from a timer tick with `advance`.
+
+use core::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::connectors::Connector;
+use crate::fade::delay_us;
+use crate::framebuffer::FrameBuffer;
+use crate::{DataError, MAX_DIGITS, MAX7219};
+
+///
+/// Monochrome bitmap, rows stored top to bottom with the leftmost pixel
+/// in the highest bit. Each row takes `(width + 7) / 8` bytes.
+///
+/// Without a mask only lit pixels are drawn, the others are transparent.
+/// With a mask pixels with their mask bit set are drawn lit or dark,
+/// the others are transparent.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sprite<'a> {
+    width: usize,
+    height: usize,
+    data: &'a [u8],
+    mask: Option<&'a [u8]>,
+}
+
+impl<'a> Sprite<'a> {
+    ///
+    /// Construct a new sprite
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - width in pixels
+    /// * `height` - height in pixels
+    /// * `data` - pixel rows, `(width + 7) / 8` bytes each
+    ///
+    /// # Panics
+    ///
+    /// * if `data` is too short for given size
+    ///
+    pub const fn new(width: usize, height: usize, data: &'a [u8]) -> Self {
+        assert!(
+            data.len() >= width.div_ceil(8) * height,
+            "sprite data too short"
+        );
+
+        Sprite {
+            width,
+            height,
+            data,
+            mask: None,
+        }
+    }
+
+    ///
+    /// Adds a transparency mask laid out like the pixel data
+    ///
+    /// # Panics
+    ///
+    /// * if `mask` is shorter than the pixel data
+    ///
+    pub const fn with_mask(mut self, mask: &'a [u8]) -> Self {
+        assert!(
+            mask.len() >= self.width.div_ceil(8) * self.height,
+            "sprite mask too short"
+        );
+
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Width in pixels
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    ///
+    /// Returns whether a pixel is lit, out of range pixels are dark
+    ///
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.bit(self.data, x, y)
+    }
+
+    ///
+    /// Returns whether a pixel is drawn when the sprite is blitted,
+    /// out of range pixels are transparent
+    ///
+    pub fn is_opaque(&self, x: usize, y: usize) -> bool {
+        match self.mask {
+            Some(mask) => self.bit(mask, x, y),
+            None => self.pixel(x, y),
+        }
+    }
+
+    fn bit(&self, bytes: &[u8], x: usize, y: usize) -> bool {
+        x < self.width
+            && y < self.height
+            && bytes[y * self.width.div_ceil(8) + x / 8] & (0b1000_0000 >> (x % 8)) != 0
+    }
+}
+
+/// A single animation frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AnimationFrame<'a> {
+    /// Sprite shown during this frame
+    pub sprite: Sprite<'a>,
+    /// Time this frame is shown
+    pub duration: Duration,
+}
+
+impl<'a> AnimationFrame<'a> {
+    ///
+    /// Construct a new animation frame
+    ///
+    /// # Arguments
+    ///
+    /// * `sprite` - sprite shown during this frame
+    /// * `duration` - time this frame is shown
+    ///
+    pub const fn new(sprite: Sprite<'a>, duration: Duration) -> Self {
+        AnimationFrame { sprite, duration }
+    }
+}
+
+///
+/// Sequence of animation frames
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Animation<'a> {
+    frames: &'a [AnimationFrame<'a>],
+    looping: bool,
+}
+
+impl<'a> Animation<'a> {
+    ///
+    /// Construct a new animation played once
+    ///
+    /// # Panics
+    ///
+    /// * if `frames` is empty
+    ///
+    pub const fn new(frames: &'a [AnimationFrame<'a>]) -> Self {
+        assert!(!frames.is_empty(), "animation without frames");
+
+        Animation {
+            frames,
+            looping: false,
+        }
+    }
+
+    ///
+    /// Makes the animation start over after its last frame
+    ///
+    pub const fn looped(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+
+    /// Frames of the animation
+    pub const fn frames(&self) -> &'a [AnimationFrame<'a>] {
+        self.frames
+    }
+
+    /// Whether the animation starts over after its last frame
+    pub const fn is_looping(&self) -> bool {
+        self.looping
+    }
+}
+
+///
+/// Plays an animation at a position on the frame buffer.
+/// The position may lie across display boundaries or partially outside the frame.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Player<'a> {
+    animation: Animation<'a>,
+    index: usize,
+    elapsed: Duration,
+    finished: bool,
+    /// Column of the left animation edge
+    pub x: i32,
+    /// Row of the top animation edge
+    pub y: i32,
+}
+
+impl<'a> Player<'a> {
+    ///
+    /// Construct a new player starting at the first frame
+    ///
+    /// # Arguments
+    ///
+    /// * `animation` - the animation to play
+    /// * `x` - column of the left animation edge, may be negative
+    /// * `y` - row of the top animation edge, may be negative
+    ///
+    pub const fn new(animation: Animation<'a>, x: i32, y: i32) -> Self {
+        Player {
+            animation,
+            index: 0,
+            elapsed: Duration::ZERO,
+            finished: false,
+            x,
+            y,
+        }
+    }
+
+    /// Current frame
+    pub fn frame(&self) -> &AnimationFrame<'a> {
+        &self.animation.frames[self.index]
+    }
+
+    /// Time until the current frame ends
+    pub fn remaining(&self) -> Duration {
+        self.frame().duration.saturating_sub(self.elapsed)
+    }
+
+    /// Whether a non looping animation passed its last frame
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    ///
+    /// Starts playback over at the first frame
+    ///
+    pub fn restart(&mut self) {
+        self.index = 0;
+        self.elapsed = Duration::ZERO;
+        self.finished = false;
+    }
+
+    ///
+    /// Advances playback by elapsed time, returns whether the current frame changed.
+    /// A finished animation keeps showing its last frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - time since the previous call
+    ///
+    pub fn advance(&mut self, elapsed: Duration) -> bool {
+        let start = self.index;
+        self.elapsed = self.elapsed.saturating_add(elapsed);
+
+        // whole loops end at the frame they started at
+        let total: Duration = self
+            .animation
+            .frames
+            .iter()
+            .map(|frame| frame.duration)
+            .sum();
+        if self.animation.looping && !total.is_zero() && self.elapsed >= total {
+            let rest = self.elapsed.as_nanos() % total.as_nanos();
+            self.elapsed = Duration::from_nanos(rest as u64);
+        }
+
+        // at most one pass, frames without duration would loop forever
+        for _ in 0..self.animation.frames.len() {
+            if self.finished || self.elapsed < self.frame().duration {
+                break;
+            }
+            self.elapsed -= self.frame().duration;
+            self.next_frame();
+        }
+        if total.is_zero() {
+            self.elapsed = Duration::ZERO;
+        }
+
+        self.index != start
+    }
+
+    ///
+    /// Draws the current frame onto the frame buffer
+    ///
+    pub fn draw<const D: usize>(&self, framebuffer: &mut FrameBuffer<D>) {
+        framebuffer.draw_sprite(&self.frame().sprite, self.x, self.y);
+    }
+
+    ///
+    /// Plays the animation to its end, drawing every frame over given background.
+    /// Only rows changed between frames are sent. Looping animations never end.
+    ///
+    /// # Arguments
+    ///
+    /// * `framebuffer` - the frame buffer to draw into
+    /// * `background` - raw display bytes the frames are drawn over
+    /// * `display` - the driver to write through
+    /// * `delay` - delay provider used to time the frames
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn play<const D: usize, CONNECTOR, DELAY>(
+        &mut self,
+        framebuffer: &mut FrameBuffer<D>,
+        background: &[[u8; MAX_DIGITS]; D],
+        display: &mut MAX7219<D, CONNECTOR>,
+        delay: &mut DELAY,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+        DELAY: DelayNs,
+    {
+        while !self.finished {
+            framebuffer.set_frame(background);
+            self.draw(framebuffer);
+            framebuffer.flush(display).await?;
+
+            delay_us(delay, self.remaining().as_micros() as u64).await;
+            self.elapsed = Duration::ZERO;
+            self.next_frame();
+        }
+
+        Ok(())
+    }
+
+    fn next_frame(&mut self) {
+        if self.index + 1 < self.animation.frames.len() {
+            self.index += 1;
+        } else if self.animation.looping {
+            self.index = 0;
+        } else {
+            self.finished = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPRITE: Sprite = Sprite::new(1, 1, &[0x80]);
+
+    fn frames(durations: [u64; 3]) -> [AnimationFrame<'static>; 3] {
+        durations.map(|ms| AnimationFrame::new(SPRITE, Duration::from_millis(ms)))
+    }
+
+    #[test]
+    fn reads_out_of_range_pixels_dark() {
+        let sprite = Sprite::new(9, 1, &[0xFF, 0x80, 0xFF]).with_mask(&[0xFF, 0x80, 0xFF]);
+
+        assert!(sprite.pixel(8, 0));
+        assert!(!sprite.pixel(9, 0));
+        assert!(!sprite.pixel(0, 1));
+        assert!(!sprite.is_opaque(0, 1));
+    }
+
+    #[test]
+    fn advances_through_frames() {
+        let frames = frames([10, 20, 30]);
+        let mut player = Player::new(Animation::new(&frames), 0, 0);
+
+        assert!(!player.advance(Duration::from_millis(5)));
+        assert!(player.advance(Duration::from_millis(5)));
+        assert_eq!(player.remaining(), Duration::from_millis(20));
+        assert!(player.advance(Duration::from_millis(50)));
+        assert!(player.is_finished());
+        assert!(!player.advance(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn skips_whole_loops() {
+        let frames = frames([10, 20, 30]);
+        let mut player = Player::new(Animation::new(&frames).looped(), 0, 0);
+
+        // 1000 loops and 15 ms into the second frame
+        assert!(player.advance(Duration::from_millis(60_025)));
+        assert_eq!(player.frame().duration, Duration::from_millis(20));
+        assert_eq!(player.remaining(), Duration::from_millis(5));
+        assert!(!player.is_finished());
+    }
+
+    #[test]
+    fn terminates_without_durations() {
+        let frames = frames([0, 0, 0]);
+        let mut player = Player::new(Animation::new(&frames).looped(), 0, 0);
+
+        assert!(!player.advance(Duration::from_millis(10)));
+        assert!(!player.advance(Duration::ZERO));
+        assert!(!player.is_finished());
+
+        let mut player = Player::new(Animation::new(&frames), 0, 0);
+        player.advance(Duration::ZERO);
+        assert!(player.is_finished());
+    }
+}
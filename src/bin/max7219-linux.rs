@@ -0,0 +1,190 @@
+//! Drives MAX7219 displays from a Linux host
+//!
+//! ```text
+//! max7219-linux [--spi PATH] [--cs CHIP:LINE] [--displays 1|2|4|8] <COMMAND> [ARGS]
+//! ```
+//!
+//! Commands are `init`, `text ADDR TEXT`, `number ADDR VALUE`, `intensity VALUE`,
+//! `test on|off`, `clear` and `off`. `--spi -` writes the raw transfers to
+//! standard output instead of a spidev device.
+
+use std::future::Future;
+use std::io::Write;
+use std::pin::pin;
+use std::process::ExitCode;
+use std::task::{Context, Poll, Waker};
+
+use embedded_hal::digital::{ErrorKind, ErrorType, OutputPin};
+use linux_embedded_hal::gpio_cdev::{Chip, LineRequestFlags};
+use linux_embedded_hal::spidev::Spidev;
+use max7219::MAX7219;
+use max7219::linux::{CdevPin, IoConnector, TransportCs, spi_options};
+
+const USAGE: &str = "usage: max7219-linux [--spi PATH] [--cs CHIP:LINE] [--displays 1|2|4|8] \
+                     <init | text ADDR TEXT | number ADDR VALUE | intensity VALUE | \
+                     test on|off | clear | off>";
+
+// CS driven by a GPIO line or by the transport
+enum Cs {
+    Gpio(CdevPin),
+    Transport(TransportCs),
+}
+
+impl ErrorType for Cs {
+    type Error = ErrorKind;
+}
+
+impl OutputPin for Cs {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        match self {
+            Cs::Gpio(pin) => pin.set_low().map_err(|_| ErrorKind::Other),
+            Cs::Transport(pin) => pin.set_low(),
+        }
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        match self {
+            Cs::Gpio(pin) => pin.set_high().map_err(|_| ErrorKind::Other),
+            Cs::Transport(pin) => pin.set_high(),
+        }
+    }
+}
+
+type Display<const D: usize> = MAX7219<D, IoConnector<Box<dyn Write>, Cs>>;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("max7219-linux: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut spi = String::from("/dev/spidev0.0");
+    let mut cs = None;
+    let mut displays = 1;
+    let mut command = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--spi" => spi = value()?,
+            "--cs" => cs = Some(value()?),
+            "--displays" => displays = value()?.parse().map_err(|_| USAGE)?,
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ => command.push(arg),
+        }
+    }
+
+    let cs = match cs {
+        Some(cs) => {
+            let (chip, line) = cs.rsplit_once(':').ok_or(USAGE)?;
+            let line = line.parse().map_err(|_| USAGE)?;
+            let pin = Chip::new(chip)
+                .and_then(|mut chip| chip.get_line(line))
+                .and_then(|line| line.request(LineRequestFlags::OUTPUT, 1, "max7219"))
+                .and_then(CdevPin::new)
+                .map_err(|e| format!("{cs}: {e}"))?;
+            Cs::Gpio(pin)
+        }
+        None => Cs::Transport(TransportCs),
+    };
+
+    let transport: Box<dyn Write> = if spi == "-" {
+        Box::new(std::io::stdout())
+    } else {
+        let kernel_cs = matches!(cs, Cs::Transport(_));
+        let mut device = Spidev::open(&spi).map_err(|e| format!("{spi}: {e}"))?;
+        device
+            .configure(&spi_options(kernel_cs))
+            .map_err(|e| format!("{spi}: {e}"))?;
+        Box::new(device)
+    };
+
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+    match displays {
+        1 => execute::<1>(MAX7219::from_io(transport, cs), &command),
+        2 => execute::<2>(MAX7219::from_io(transport, cs), &command),
+        4 => execute::<4>(MAX7219::from_io(transport, cs), &command),
+        8 => execute::<8>(MAX7219::from_io(transport, cs), &command),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn execute<const D: usize>(mut display: Display<D>, command: &[&str]) -> Result<(), String> {
+    let addr = match command {
+        ["text" | "number", addr, _] => match addr.parse() {
+            Ok(addr) if addr < D => addr,
+            _ => return Err(format!("address must be below {D}")),
+        },
+        _ => 0,
+    };
+    let number = match command {
+        ["number" | "intensity", .., value] => value
+            .parse::<i32>()
+            .map_err(|_| format!("invalid number {value}"))?,
+        _ => 0,
+    };
+    if matches!(command, ["intensity", _]) && !(0..=0x0F).contains(&number) {
+        return Err("intensity must be from 0 to 15".into());
+    }
+
+    if !matches!(
+        command,
+        ["init" | "clear" | "off"]
+            | ["text" | "number", _, _]
+            | ["intensity", _]
+            | ["test", "on" | "off"]
+    ) {
+        return Err(USAGE.into());
+    }
+
+    let transfer = async {
+        match command {
+            ["init"] => {
+                display.init().await?;
+                display.power_on().await
+            }
+            ["text", _, text] => {
+                let mut bytes = [b' '; 8];
+                for (byte, c) in bytes.iter_mut().zip(text.bytes()) {
+                    *byte = c;
+                }
+                display.write_str(addr, &bytes, 0).await?;
+                display.power_on().await
+            }
+            ["number", _, _] => {
+                display.write_integer(addr, number).await?;
+                display.power_on().await
+            }
+            ["intensity", _] => display.set_intensity_all(number as u8).await,
+            ["test", state @ ("on" | "off")] => {
+                for addr in 0..D {
+                    display.test(addr, *state == "on").await?;
+                }
+                Ok(())
+            }
+            ["clear"] => display.clear_all_displays().await,
+            ["off"] => display.power_off().await,
+            _ => unreachable!("command validated above"),
+        }
+    };
+
+    block_on(transfer).map_err(|e| format!("transfer failed: {e:?}"))
+}
+
+// runs a future to completion, the connector never waits so busy polling is fine
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
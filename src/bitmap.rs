@@ -0,0 +1,172 @@
+//! Compile time bitmaps drawn as ASCII art
+//!
+//! Rows are written top to bottom with `#` for a lit and `.` for a dark pixel.
+//! [`bitmap!`](crate::bitmap!) turns 8 rows of 8 pixels into the raw bytes
+//! expected by `write_digits`, [`bitmap_strip!`](crate::bitmap_strip!) turns
+//! wider rows into one raw frame per module as used by `write_frame`.
+//! Misdrawn bitmaps fail to compile when used in a constant.
+//!
+//! ```
+//! const SMILEY: [u8; 8] = max7219::bitmap![
+//!     "..####..",
+//!     ".#....#.",
+//!     "#.#..#.#",
+//!     "#......#",
+//!     "#.#..#.#",
+//!     "#..##..#",
+//!     ".#....#.",
+//!     "..####..",
+//! ];
+//!
+//! assert_eq!(SMILEY[0], 0b0011_1100);
+//! ```
+//!
+//! A row one pixel short is a compile error:
+//!
+//! ```compile_fail
+//! let _ = max7219::bitmap![
+//!     "########", "########", "########", "#######",
+//!     "########", "########", "########", "########",
+//! ];
+//! ```
+//!
+//! So is a strip row of a different width than the first:
+//!
+//! ```compile_fail
+//! let _ = max7219::bitmap_strip![
+//!     "################", "################", "################", "################",
+//!     "################", "################", "################", "########",
+//! ];
+//! ```
+
+use crate::MAX_DIGITS;
+
+///
+/// Converts 8 rows of 8 pixels into raw display bytes, row 0 going into digit 0
+///
+/// # Panics
+///
+/// * if there are not exactly 8 rows, a row is not 8 pixels wide
+///   or contains characters other than `#` and `.`
+///
+pub const fn from_rows(rows: &[&str]) -> [u8; MAX_DIGITS] {
+    assert!(rows.len() == MAX_DIGITS, "bitmap must have 8 rows");
+
+    let strip: [[u8; MAX_DIGITS]; 1] = strip_from_rows(rows);
+    strip[0]
+}
+
+///
+/// Returns the number of 8x8 modules a strip of rows spans
+///
+/// # Panics
+///
+/// * if there are no rows or the first row is not a multiple of 8 pixels wide
+///
+pub const fn strip_modules(rows: &[&str]) -> usize {
+    assert!(!rows.is_empty(), "bitmap must have rows");
+
+    let width = rows[0].len();
+    assert!(
+        width > 0 && width.is_multiple_of(8),
+        "bitmap strip rows must be a multiple of 8 pixels wide"
+    );
+
+    width / 8
+}
+
+///
+/// Converts 8 rows spanning `N` modules into raw display bytes per module,
+/// the leftmost 8 pixels going to module 0
+///
+/// # Panics
+///
+/// * if there are not exactly 8 rows, a row is not `N * 8` pixels wide
+///   or contains characters other than `#` and `.`
+///
+pub const fn strip_from_rows<const N: usize>(rows: &[&str]) -> [[u8; MAX_DIGITS]; N] {
+    assert!(rows.len() == MAX_DIGITS, "bitmap must have 8 rows");
+
+    let mut frame = [[0x00; MAX_DIGITS]; N];
+    let mut y = 0;
+    while y < MAX_DIGITS {
+        let row = rows[y].as_bytes();
+        assert!(row.len() == N * 8, "bitmap row has the wrong width");
+
+        let mut x = 0;
+        while x < row.len() {
+            match row[x] {
+                b'#' => frame[x / 8][y] |= 0b1000_0000 >> (x % 8),
+                b'.' => {}
+                _ => panic!("bitmap pixels must be '#' or '.'"),
+            }
+            x += 1;
+        }
+        y += 1;
+    }
+
+    frame
+}
+
+///
+/// Builds the raw `[u8; 8]` bytes of an 8x8 bitmap from ASCII art rows at compile time
+///
+#[macro_export]
+macro_rules! bitmap {
+    ($($row:literal),+ $(,)?) => {{
+        const BITMAP: [u8; 8] = $crate::bitmap::from_rows(&[$($row),+]);
+        BITMAP
+    }};
+}
+
+///
+/// Builds raw `[[u8; 8]; N]` module frames of a bitmap spanning `N` modules
+/// from ASCII art rows at compile time
+///
+#[macro_export]
+macro_rules! bitmap_strip {
+    ($($row:literal),+ $(,)?) => {{
+        const ROWS: &[&str] = &[$($row),+];
+        const MODULES: usize = $crate::bitmap::strip_modules(ROWS);
+        const STRIP: [[u8; 8]; MODULES] = $crate::bitmap::strip_from_rows(ROWS);
+        STRIP
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn builds_strips_per_module() {
+        let strip = crate::bitmap_strip![
+            "#..............#",
+            ".#............#.",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "########........",
+        ];
+
+        assert_eq!(strip.len(), 2);
+        assert_eq!(strip[0], [0x80, 0x40, 0, 0, 0, 0, 0, 0xFF]);
+        assert_eq!(strip[1], [0x01, 0x02, 0, 0, 0, 0, 0, 0x00]);
+    }
+
+    #[test]
+    fn builds_single_bitmaps() {
+        #[rustfmt::skip]
+        let bitmap = crate::bitmap![
+            "#.......",
+            "........",
+            "........",
+            "........",
+            "........",
+            "........",
+            "........",
+            ".......#",
+        ];
+
+        assert_eq!(bitmap, [0x80, 0, 0, 0, 0, 0, 0, 0x01]);
+    }
+}
@@ -0,0 +1,390 @@
+//! Ambient light driven brightness and time of day schedules
+//!
+//! [`AutoBrightness`] maps readings of a light sensor, in lux or raw ADC
+//! counts, to an intensity through a curve. Readings are smoothed and a
+//! change needs to clear the hysteresis margin, so a flickering light source
+//! does not make the displays flicker along. Units without a sensor can use
+//! a [`BrightnessSchedule`] instead. Both set the intensity chain-wide and
+//! only write when the displays are not set to it already.
+
+use crate::connectors::Connector;
+use crate::fade::MAX_INTENSITY;
+use crate::{DataError, MAX7219};
+
+/// Minutes in a day
+const DAY_MINUTES: u16 = 24 * 60;
+
+///
+/// Maps ambient light readings to an intensity applied to all displays
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AutoBrightness<const N: usize> {
+    curve: [(u32, u8); N],
+    hysteresis: u32,
+    smoothing: u8,
+    // smoothed reading scaled by 2^smoothing
+    average: Option<u64>,
+    intensity: Option<u8>,
+}
+
+impl<const N: usize> AutoBrightness<N> {
+    ///
+    /// Construct a new controller without hysteresis and smoothing
+    ///
+    /// # Arguments
+    ///
+    /// * `curve` - `(reading, intensity)` points by ascending reading, intensity is
+    ///   interpolated between them and kept at the first and last one outside
+    ///
+    /// # Panics
+    ///
+    /// * if the points are not sorted by reading
+    ///
+    pub const fn new(curve: [(u32, u8); N]) -> Self {
+        let mut i = 1;
+        while i < N {
+            assert!(
+                curve[i - 1].0 <= curve[i].0,
+                "curve must be sorted by reading"
+            );
+            i += 1;
+        }
+
+        AutoBrightness {
+            curve,
+            hysteresis: 0,
+            smoothing: 0,
+            average: None,
+            intensity: None,
+        }
+    }
+
+    ///
+    /// Only changes intensity once the reading moved past the point of change by a margin
+    ///
+    /// # Arguments
+    ///
+    /// * `hysteresis` - margin in reading units
+    ///
+    pub const fn with_hysteresis(mut self, hysteresis: u32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    ///
+    /// Smooths readings with an exponential moving average, each reading
+    /// moving the average by `1 / 2^smoothing` of the difference
+    ///
+    /// # Arguments
+    ///
+    /// * `smoothing` - weight of the average from `0` (off) to `16`
+    ///
+    pub const fn with_smoothing(mut self, smoothing: u8) -> Self {
+        self.smoothing = if smoothing > 16 { 16 } else { smoothing };
+        self
+    }
+
+    /// The smoothed reading, `None` before the first update
+    pub fn reading(&self) -> Option<u32> {
+        self.average
+            .map(|average| (average >> self.smoothing) as u32)
+    }
+
+    /// The intensity last chosen, `None` before the first update
+    pub fn intensity(&self) -> Option<u8> {
+        self.intensity
+    }
+
+    ///
+    /// Intensity on the curve for a reading
+    ///
+    /// # Arguments
+    ///
+    /// * `reading` - the ambient light reading
+    ///
+    pub fn map(&self, reading: u32) -> u8 {
+        let Some(last) = self.curve.last() else {
+            return MAX_INTENSITY;
+        };
+
+        let intensity = match self.curve.iter().position(|(at, _)| reading < *at) {
+            Some(0) => self.curve[0].1,
+            Some(i) => {
+                let (from, low) = self.curve[i - 1];
+                let (to, high) = self.curve[i];
+                let offset = (reading - from) as i64 * (high as i64 - low as i64);
+                (low as i64 + offset / (to - from) as i64) as u8
+            }
+            None => last.1,
+        };
+
+        intensity.min(MAX_INTENSITY)
+    }
+
+    ///
+    /// Takes a new reading and sets the resulting intensity on all displays
+    /// unless they are set to it already, also after being changed by other
+    /// means. Returns the intensity if it was written.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to set intensity on
+    /// * `reading` - the ambient light reading
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn update<const D: usize, CONNECTOR>(
+        &mut self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        reading: u32,
+    ) -> Result<Option<u8>, DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let average = match self.average {
+            Some(average) => average - (average >> self.smoothing) + reading as u64,
+            None => (reading as u64) << self.smoothing,
+        };
+        self.average = Some(average);
+        let reading = (average >> self.smoothing) as u32;
+
+        let target = self.map(reading);
+        let intensity = match self.intensity {
+            // the reading has to clear the hysteresis towards the new intensity
+            Some(current) if target > current => {
+                current.max(self.map(reading.saturating_sub(self.hysteresis)))
+            }
+            Some(current) if target < current => {
+                current.min(self.map(reading.saturating_add(self.hysteresis)))
+            }
+            Some(current) => current,
+            None => target,
+        };
+
+        self.intensity = Some(intensity);
+        if is_set(display, intensity) {
+            return Ok(None);
+        }
+
+        display.set_intensity_all(intensity).await?;
+
+        Ok(Some(intensity))
+    }
+}
+
+///
+/// Intensity by time of day for units without a light sensor
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BrightnessSchedule<const N: usize> {
+    entries: [(u16, u8); N],
+}
+
+impl<const N: usize> BrightnessSchedule<N> {
+    ///
+    /// Construct a new schedule
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - `(minute of day, intensity)` pairs by ascending minute, each intensity
+    ///   holds until the next entry, the last one until the first one on the next day
+    ///
+    /// # Panics
+    ///
+    /// * if the entries are not sorted by minute
+    ///
+    pub const fn new(entries: [(u16, u8); N]) -> Self {
+        let mut i = 1;
+        while i < N {
+            assert!(
+                entries[i - 1].0 <= entries[i].0,
+                "entries must be sorted by minute"
+            );
+            i += 1;
+        }
+
+        BrightnessSchedule { entries }
+    }
+
+    ///
+    /// Intensity scheduled at a time of day
+    ///
+    /// # Arguments
+    ///
+    /// * `minute` - minutes since midnight
+    ///
+    pub fn intensity_at(&self, minute: u16) -> u8 {
+        let minute = minute % DAY_MINUTES;
+
+        self.entries
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= minute)
+            .or(self.entries.last())
+            .map_or(MAX_INTENSITY, |(_, intensity)| *intensity)
+            .min(MAX_INTENSITY)
+    }
+
+    ///
+    /// Sets the intensity scheduled at a time of day on all displays
+    /// unless they are set to it already, also after being changed by other
+    /// means. Returns the intensity if it was written.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to set intensity on
+    /// * `minute` - minutes since midnight
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn update<const D: usize, CONNECTOR>(
+        &mut self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        minute: u16,
+    ) -> Result<Option<u8>, DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let intensity = self.intensity_at(minute);
+        if is_set(display, intensity) {
+            return Ok(None);
+        }
+
+        display.set_intensity_all(intensity).await?;
+
+        Ok(Some(intensity))
+    }
+}
+
+impl BrightnessSchedule<2> {
+    ///
+    /// Construct a new night mode schedule, dimming between two times of day
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - intensity during the day
+    /// * `night` - intensity during the night
+    /// * `night_start` - minutes since midnight the night starts at
+    /// * `night_end` - minutes since midnight the night ends at
+    ///
+    pub const fn night_mode(day: u8, night: u8, night_start: u16, night_end: u16) -> Self {
+        if night_end <= night_start {
+            BrightnessSchedule::new([(night_end, day), (night_start, night)])
+        } else {
+            BrightnessSchedule::new([(night_start, night), (night_end, day)])
+        }
+    }
+}
+
+// whether all displays show an intensity with their offset applied
+fn is_set<const D: usize, CONNECTOR>(display: &MAX7219<D, CONNECTOR>, intensity: u8) -> bool
+where
+    CONNECTOR: Connector,
+{
+    (0..D).all(|addr| display.intensity(addr) == display.calibrated_intensity(addr, intensity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::testing::{block_on, recorder, take};
+
+    const CURVE: [(u32, u8); 3] = [(100, 1), (200, 5), (1000, 15)];
+
+    #[test]
+    fn maps_along_curve() {
+        let brightness = AutoBrightness::new(CURVE);
+
+        assert_eq!(brightness.map(0), 1);
+        assert_eq!(brightness.map(150), 3);
+        assert_eq!(brightness.map(200), 5);
+        assert_eq!(brightness.map(600), 10);
+        assert_eq!(brightness.map(u32::MAX), 15);
+        assert_eq!(AutoBrightness::new([]).map(10), MAX_INTENSITY);
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted")]
+    fn rejects_descending_curve() {
+        AutoBrightness::new([(200, 5), (100, 1)]);
+    }
+
+    #[test]
+    fn changes_past_hysteresis() {
+        let mut display = recorder::<1>();
+        let mut brightness = AutoBrightness::new(CURVE).with_hysteresis(50);
+        let mut update = |reading| block_on(brightness.update(&mut display, reading)).unwrap();
+
+        assert_eq!(update(200), Some(5));
+        assert_eq!(update(240), None);
+        assert_eq!(update(340), Some(6));
+        assert_eq!(update(260), None);
+        assert_eq!(update(190), Some(5));
+    }
+
+    #[test]
+    fn smooths_readings() {
+        let mut display = recorder::<1>();
+        let mut brightness = AutoBrightness::new(CURVE).with_smoothing(1);
+
+        block_on(brightness.update(&mut display, 200)).unwrap();
+        block_on(brightness.update(&mut display, 1000)).unwrap();
+
+        assert_eq!(brightness.reading(), Some(600));
+        assert_eq!(brightness.intensity(), Some(10));
+    }
+
+    #[test]
+    fn rewrites_after_outside_change() {
+        let mut display = recorder::<2>();
+        display.set_intensity_offsets([0, 2]);
+        let mut brightness = AutoBrightness::new(CURVE);
+
+        assert_eq!(
+            block_on(brightness.update(&mut display, 200)).unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            block_on(brightness.update(&mut display, 200)).unwrap(),
+            None
+        );
+        block_on(display.set_intensity(1, 0)).unwrap();
+        assert_eq!(
+            block_on(brightness.update(&mut display, 200)).unwrap(),
+            Some(5)
+        );
+
+        let intensity = Command::Intensity as u8;
+        let transfers = take(&mut display);
+        assert_eq!(transfers.last().unwrap(), &[intensity, 5, intensity, 7]);
+    }
+
+    #[test]
+    fn schedules_by_time_of_day() {
+        let schedule = BrightnessSchedule::night_mode(12, 2, 22 * 60, 6 * 60);
+
+        assert_eq!(schedule.intensity_at(0), 2);
+        assert_eq!(schedule.intensity_at(6 * 60), 12);
+        assert_eq!(schedule.intensity_at(22 * 60), 2);
+        assert_eq!(schedule.intensity_at(DAY_MINUTES + 12 * 60), 12);
+
+        let mut display = recorder::<1>();
+        let mut schedule = schedule;
+        assert_eq!(
+            block_on(schedule.update(&mut display, 60)).unwrap(),
+            Some(2)
+        );
+        assert_eq!(block_on(schedule.update(&mut display, 120)).unwrap(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted")]
+    fn rejects_unsorted_schedule() {
+        BrightnessSchedule::new([(600, 5), (60, 1)]);
+    }
+}
@@ -0,0 +1,68 @@
+//! Per display intensity compensation
+//!
+//! Modules from different batches differ in brightness at the same intensity.
+//! An offset per display corrects this, added to every intensity set on the
+//! whole chain with `set_intensity_all` and everything built on it, still in a
+//! single transfer, and to fades of a single display. Intensities set with
+//! `set_intensity` are sent unchanged.
+
+use crate::connectors::Connector;
+use crate::fade::MAX_INTENSITY;
+use crate::{DataError, MAX7219};
+
+impl<const D: usize, CONNECTOR> MAX7219<D, CONNECTOR>
+where
+    CONNECTOR: Connector,
+{
+    ///
+    /// Sets the intensity offset of each display. The current intensity is not
+    /// changed until the next chain wide intensity is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `offsets` - offset added to chain wide intensities, per display as connected in series (0 -> last)
+    ///
+    pub fn set_intensity_offsets(&mut self, offsets: [i8; D]) {
+        self.intensity_offsets = offsets;
+    }
+
+    ///
+    /// Sets the intensity offset of each display and applies it to given intensity right away
+    ///
+    /// # Arguments
+    ///
+    /// * `offsets` - offset added to chain wide intensities, per display as connected in series (0 -> last)
+    /// * `intensity` - intensity value to set to `0x00` to 0x0F`
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn calibrate_intensity(
+        &mut self,
+        offsets: [i8; D],
+        intensity: u8,
+    ) -> Result<(), DataError> {
+        self.set_intensity_offsets(offsets);
+        self.set_intensity_all(intensity).await
+    }
+
+    /// Intensity offset of each display
+    pub fn intensity_offsets(&self) -> &[i8; D] {
+        &self.intensity_offsets
+    }
+
+    ///
+    /// Intensity sent to a display for a chain wide intensity, limited to `0x00` to `0x0F`
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - display to address as connected in series (0 -> last)
+    /// * `intensity` - chain wide intensity value
+    ///
+    pub fn calibrated_intensity(&self, addr: usize, intensity: u8) -> u8 {
+        let intensity = intensity.min(MAX_INTENSITY) as i16 + self.intensity_offsets[addr] as i16;
+
+        intensity.clamp(0, MAX_INTENSITY as i16) as u8
+    }
+}
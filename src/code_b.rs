@@ -0,0 +1,73 @@
+//! Code-B font encoding as decoded by the display chip
+
+use crate::DataError;
+
+/// Code-B value of a blank digit
+pub const BLANK: u8 = 0b0000_1111;
+
+/// Code-B bit turning the decimal point on
+pub const DOT: u8 = 0b1000_0000;
+
+///
+/// Translate an ASCII byte into its Code-B value.
+/// Letters are accepted in both cases and always shown as the upper case glyph.
+///
+/// # Arguments
+///
+/// * `b` - ASCII byte consisting of [0-9,-,E,H,L,P] or space for blanking
+/// * `dot` - whether to turn the decimal point on
+///
+/// # Errors
+///
+/// * `DataError::UnsupportedChar` - returned in case the byte has no Code-B glyph
+///
+pub const fn encode(b: u8, dot: bool) -> Result<u8, DataError> {
+    let code = match b {
+        b'0'..=b'9' => b - b'0',
+        b'-' => 0b0000_1010,
+        b'e' | b'E' => 0b0000_1011,
+        b'h' | b'H' => 0b0000_1100,
+        b'l' | b'L' => 0b0000_1101,
+        b'p' | b'P' => 0b0000_1110,
+        b' ' => BLANK,
+        _ => return Err(DataError::UnsupportedChar(b)),
+    };
+
+    if dot { Ok(code | DOT) } else { Ok(code) }
+}
+
+///
+/// Checks if an ASCII byte has a Code-B glyph
+///
+pub const fn is_supported(b: u8) -> bool {
+    encode(b, false).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_glyphs() {
+        let codes: [u8; 16] =
+            core::array::from_fn(|i| encode(b"0123456789-EHLP "[i], false).unwrap());
+        let expected: [u8; 16] = core::array::from_fn(|i| i as u8);
+
+        assert_eq!(codes, expected);
+    }
+
+    #[test]
+    fn accepts_lower_case_and_dots() {
+        assert_eq!(encode(b'h', false).unwrap(), encode(b'H', false).unwrap());
+        assert_eq!(encode(b'p', true).unwrap(), 0x0E | DOT);
+        assert_eq!(encode(b' ', true).unwrap(), BLANK | DOT);
+    }
+
+    #[test]
+    fn rejects_other_bytes() {
+        for b in [b'A', b'o', b'.', b'_', 0x00, 0xFF] {
+            assert!(matches!(encode(b, false), Err(DataError::UnsupportedChar(c)) if c == b));
+            assert!(!is_supported(b));
+        }
+    }
+}
@@ -1,30 +1,136 @@
-use embedded_hal::digital::OutputPin;
-use embedded_hal_async::spi::SpiDevice;
+use embedded_hal::digital::{OutputPin, PinState};
+use embedded_hal::spi::{MODE_0, Mode};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::{SpiBus, SpiDevice};
 
 use crate::DataError;
 
+/// SPI mode expected by the MAX7219: clock idles low, data is sampled on
+/// the rising edge, most significant bit first
+pub const SPI_MODE: Mode = MODE_0;
+
+/// Maximum SPI clock frequency supported by the MAX7219
+pub const MAX_SPI_FREQUENCY_HZ: u32 = 10_000_000;
+
 /// Describes the interface used to connect to the MX7219
+#[allow(async_fn_in_trait)]
 pub trait Connector {
     ///
-    /// Writes raw bytes
+    /// Writes raw bytes. The bytes may only be latched once all of them were sent,
+    /// a transfer dropped before completion should keep the load line low so
+    /// the next complete transfer shifts the partial data out.
     ///
     /// # Errors
     ///
     /// * `DataError` - returned in case there was an error during data transfer
     ///
     async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError>;
+
+    ///
+    /// Whether a transfer failed since the last call, even if it succeeded on a retry.
+    /// The displays may then have latched partially shifted data.
+    ///
+    fn take_dirty(&mut self) -> bool {
+        false
+    }
+}
+
+/// Connector able to read DOUT of the last display in series while writing,
+/// with DOUT wired back to MISO
+#[allow(async_fn_in_trait)]
+pub trait LoopbackConnector: Connector {
+    ///
+    /// Writes raw bytes in a single transfer while reading the same number of bytes
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    async fn transfer_raw_bytes(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), DataError>;
+}
+
+///
+/// Delay free timing for `PinConnector`, pins are toggled as fast as the GPIO allows
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+///
+/// Timing profile of a bit-banged `PinConnector`, all values in nanoseconds.
+/// Zero values skip the delay.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PinTiming {
+    /// Time SCK is held high per bit
+    pub clock_high_ns: u32,
+    /// Time SCK is held low per bit, DATA is set at its start
+    pub clock_low_ns: u32,
+    /// Time from CS falling to the first SCK rising edge
+    pub cs_setup_ns: u32,
+    /// Time from the last SCK rising edge to CS rising
+    pub cs_hold_ns: u32,
+    /// Time CS is held high after latching, before the next transfer may start
+    pub load_pulse_ns: u32,
+}
+
+impl PinTiming {
+    /// No delays, pins are toggled as fast as the GPIO allows
+    pub const NONE: PinTiming = PinTiming::uniform(0);
+
+    /// Minimum timing from the MAX7219 datasheet
+    pub const DATASHEET: PinTiming = PinTiming {
+        clock_high_ns: 50,
+        clock_low_ns: 50,
+        cs_setup_ns: 25,
+        cs_hold_ns: 0,
+        load_pulse_ns: 50,
+    };
+
+    ///
+    /// Timing with the same delay everywhere, useful to slow down
+    /// transfers over long cables
+    ///
+    /// # Arguments
+    ///
+    /// * `ns` - delay in nanoseconds
+    ///
+    pub const fn uniform(ns: u32) -> Self {
+        PinTiming {
+            clock_high_ns: ns,
+            clock_low_ns: ns,
+            cs_setup_ns: ns,
+            cs_hold_ns: ns,
+            load_pulse_ns: ns,
+        }
+    }
+}
+
+impl Default for PinTiming {
+    fn default() -> Self {
+        PinTiming::DATASHEET
+    }
 }
 
-/// Direct GPIO pins connector
-pub struct PinConnector<DATA, CS, SCK>
+/// Direct GPIO pins connector. Data is shifted out MSB first and
+/// sampled on the rising SCK edge, CS (LOAD) latches on its rising edge.
+pub struct PinConnector<DATA, CS, SCK, DELAY = NoDelay>
 where
     DATA: OutputPin,
     CS: OutputPin,
     SCK: OutputPin,
+    DELAY: DelayNs,
 {
     data: DATA,
     cs: CS,
     sck: SCK,
+    delay: DELAY,
+    timing: PinTiming,
+    clock_idle: PinState,
+    data_idle: PinState,
 }
 
 impl<DATA, CS, SCK> PinConnector<DATA, CS, SCK>
@@ -34,36 +140,102 @@ where
     SCK: OutputPin,
 {
     pub(crate) fn new(data: DATA, cs: CS, sck: SCK) -> Self {
-        PinConnector { data, cs, sck }
+        PinConnector {
+            data,
+            cs,
+            sck,
+            delay: NoDelay,
+            timing: PinTiming::NONE,
+            clock_idle: PinState::Low,
+            data_idle: PinState::Low,
+        }
     }
 }
 
-impl<DATA, CS, SCK> Connector for PinConnector<DATA, CS, SCK>
+impl<DATA, CS, SCK, DELAY> PinConnector<DATA, CS, SCK, DELAY>
 where
     DATA: OutputPin,
     CS: OutputPin,
     SCK: OutputPin,
+    DELAY: DelayNs,
+{
+    pub(crate) fn with_timing<T>(
+        self,
+        delay: T,
+        timing: PinTiming,
+    ) -> PinConnector<DATA, CS, SCK, T>
+    where
+        T: DelayNs,
+    {
+        PinConnector {
+            data: self.data,
+            cs: self.cs,
+            sck: self.sck,
+            delay,
+            timing,
+            clock_idle: self.clock_idle,
+            data_idle: self.data_idle,
+        }
+    }
+
+    pub(crate) fn set_idle_levels(&mut self, clock: PinState, data: PinState) {
+        self.clock_idle = clock;
+        self.data_idle = data;
+    }
+
+    async fn pause(&mut self, ns: u32) {
+        if ns > 0 {
+            self.delay.delay_ns(ns).await;
+        }
+    }
+}
+
+impl<DATA, CS, SCK, DELAY> Connector for PinConnector<DATA, CS, SCK, DELAY>
+where
+    DATA: OutputPin,
+    CS: OutputPin,
+    SCK: OutputPin,
+    DELAY: DelayNs,
 {
     async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+        // SCK must be at its idle level before CS falls to avoid a spurious edge
+        self.sck
+            .set_state(self.clock_idle)
+            .map_err(|_| DataError::Pin)?;
         self.cs.set_low().map_err(|_| DataError::Pin)?;
+        self.pause(self.timing.cs_setup_ns).await;
+
         for byte in bytes {
             for i in 0..8 {
+                self.sck.set_low().map_err(|_| DataError::Pin)?;
                 if byte & (1 << (7 - i)) > 0 {
                     self.data.set_high().map_err(|_| DataError::Pin)?;
                 } else {
                     self.data.set_low().map_err(|_| DataError::Pin)?;
                 }
+                self.pause(self.timing.clock_low_ns).await;
 
                 self.sck.set_high().map_err(|_| DataError::Pin)?;
-                self.sck.set_low().map_err(|_| DataError::Pin)?;
+                self.pause(self.timing.clock_high_ns).await;
             }
         }
+
+        self.pause(self.timing.cs_hold_ns).await;
         self.cs.set_high().map_err(|_| DataError::Pin)?;
+        self.sck
+            .set_state(self.clock_idle)
+            .map_err(|_| DataError::Pin)?;
+        self.data
+            .set_state(self.data_idle)
+            .map_err(|_| DataError::Pin)?;
+        self.pause(self.timing.load_pulse_ns).await;
 
         Ok(())
     }
 }
 
+/// Hardware controlled CS connector with SPI transfer.
+/// The device must be configured with [`SPI_MODE`] and at most [`MAX_SPI_FREQUENCY_HZ`].
 pub struct SpiConnector<SPI>
 where
     SPI: SpiDevice<u8>,
@@ -71,7 +243,6 @@ where
     spi: SPI,
 }
 
-/// Hardware controlled CS connector with SPI transfer
 impl<SPI> SpiConnector<SPI>
 where
     SPI: SpiDevice<u8>,
@@ -91,7 +262,10 @@ where
     }
 }
 
-/// Software controlled CS connector with SPI transfer
+/// Software controlled CS connector with SPI transfer. The CS pin is toggled
+/// around the device transaction, so the device itself should not drive CS
+/// of the display. Prefer `SpiBusConnector` when owning the bus.
+/// The device must be configured with [`SPI_MODE`] and at most [`MAX_SPI_FREQUENCY_HZ`].
 pub struct SpiConnectorSW<SPI, CS>
 where
     SPI: SpiDevice<u8>,
@@ -101,6 +275,18 @@ where
     cs: CS,
 }
 
+impl<SPI> LoopbackConnector for SpiConnector<SPI>
+where
+    SPI: SpiDevice<u8>,
+{
+    async fn transfer_raw_bytes(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), DataError> {
+        self.spi
+            .transfer(read, write)
+            .await
+            .map_err(|_| DataError::Spi)
+    }
+}
+
 impl<SPI, CS> SpiConnectorSW<SPI, CS>
 where
     SPI: SpiDevice<u8>,
@@ -130,3 +316,608 @@ where
         Ok(())
     }
 }
+
+impl<SPI, CS> LoopbackConnector for SpiConnectorSW<SPI, CS>
+where
+    SPI: SpiDevice<u8>,
+    CS: OutputPin,
+{
+    async fn transfer_raw_bytes(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), DataError> {
+        self.cs.set_low().map_err(|_| DataError::Pin)?;
+        self.spi_c.transfer_raw_bytes(read, write).await?;
+        self.cs.set_high().map_err(|_| DataError::Pin)?;
+
+        Ok(())
+    }
+}
+
+/// Exclusive SPI bus connector with software controlled CS.
+/// The bus must be configured with [`SPI_MODE`] and at most [`MAX_SPI_FREQUENCY_HZ`].
+pub struct SpiBusConnector<BUS, CS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    bus: BUS,
+    cs: CS,
+}
+
+impl<BUS, CS> SpiBusConnector<BUS, CS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    pub(crate) fn new(bus: BUS, cs: CS) -> Self {
+        SpiBusConnector { bus, cs }
+    }
+}
+
+impl<BUS, CS> Connector for SpiBusConnector<BUS, CS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+        self.cs.set_low().map_err(|_| DataError::Pin)?;
+        self.bus.write(bytes).await.map_err(|_| DataError::Spi)?;
+        // all bytes must be out before CS latches them
+        self.bus.flush().await.map_err(|_| DataError::Spi)?;
+        self.cs.set_high().map_err(|_| DataError::Pin)?;
+
+        Ok(())
+    }
+}
+
+impl<BUS, CS> LoopbackConnector for SpiBusConnector<BUS, CS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    async fn transfer_raw_bytes(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), DataError> {
+        self.cs.set_low().map_err(|_| DataError::Pin)?;
+        self.bus
+            .transfer(read, write)
+            .await
+            .map_err(|_| DataError::Spi)?;
+        self.bus.flush().await.map_err(|_| DataError::Spi)?;
+        self.cs.set_high().map_err(|_| DataError::Pin)?;
+
+        Ok(())
+    }
+}
+
+/// Blocking SPI device connector, for buses shared through the `embedded-hal-bus`
+/// device wrappers such as `RefCellDevice` or `CriticalSectionDevice`.
+/// Transfers block until complete and can not be interrupted.
+/// The bus must be configured with [`SPI_MODE`] and at most [`MAX_SPI_FREQUENCY_HZ`].
+pub struct BlockingSpiConnector<SPI>
+where
+    SPI: embedded_hal::spi::SpiDevice<u8>,
+{
+    spi: SPI,
+}
+
+impl<SPI> BlockingSpiConnector<SPI>
+where
+    SPI: embedded_hal::spi::SpiDevice<u8>,
+{
+    pub(crate) fn new(spi: SPI) -> Self {
+        BlockingSpiConnector { spi }
+    }
+}
+
+impl<SPI> Connector for BlockingSpiConnector<SPI>
+where
+    SPI: embedded_hal::spi::SpiDevice<u8>,
+{
+    async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+        self.spi.write(bytes).map_err(|_| DataError::Spi)?;
+
+        Ok(())
+    }
+}
+
+impl<SPI> LoopbackConnector for BlockingSpiConnector<SPI>
+where
+    SPI: embedded_hal::spi::SpiDevice<u8>,
+{
+    async fn transfer_raw_bytes(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), DataError> {
+        self.spi.transfer(read, write).map_err(|_| DataError::Spi)
+    }
+}
+
+/// Several chains of equal length on one exclusively owned SPI bus, each chain
+/// with its own CS (LOAD) line. Displays are addressed flat, chain 0 holding
+/// the first `D / CHAINS` addresses. Each transfer is split per chain and chains
+/// without a register to write are skipped, so a transfer to a single display
+/// only clocks its own chain. Transfers must cover the whole set of chains,
+/// two bytes per display as the driver always writes, others are rejected.
+/// The bus must be configured with [`SPI_MODE`] and at most [`MAX_SPI_FREQUENCY_HZ`].
+pub struct MultiChainConnector<BUS, CS, const CHAINS: usize>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    bus: BUS,
+    cs: [CS; CHAINS],
+}
+
+impl<BUS, CS, const CHAINS: usize> MultiChainConnector<BUS, CS, CHAINS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    pub(crate) fn new(bus: BUS, cs: [CS; CHAINS]) -> Self {
+        MultiChainConnector { bus, cs }
+    }
+}
+
+impl<BUS, CS, const CHAINS: usize> Connector for MultiChainConnector<BUS, CS, CHAINS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+        // a partial transfer can not be split into whole displays per chain
+        if bytes.len().checked_rem(CHAINS * 2) != Some(0) {
+            return Err(DataError::Spi);
+        }
+        let chain_len = bytes.len() / CHAINS;
+
+        for (cs, chunk) in self.cs.iter_mut().zip(bytes.chunks(chain_len.max(1))) {
+            // every header is a no-op
+            if chunk.iter().step_by(2).all(|header| header & 0x0F == 0x00) {
+                continue;
+            }
+
+            cs.set_low().map_err(|_| DataError::Pin)?;
+            self.bus.write(chunk).await.map_err(|_| DataError::Spi)?;
+            self.bus.flush().await.map_err(|_| DataError::Spi)?;
+            cs.set_high().map_err(|_| DataError::Pin)?;
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// Retries of failed transfers. Each retry waits for the backoff,
+/// which doubles after every attempt.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts per transfer, at least one is always made
+    pub attempts: u8,
+    /// Delay before the first retry in microseconds
+    pub backoff_us: u32,
+    /// Whether a failed attempt marks the displays out of sync, so the next
+    /// write rewrites all registers first
+    pub mark_dirty: bool,
+}
+
+impl RetryPolicy {
+    /// No retries, a failed transfer is returned right away
+    pub const NONE: RetryPolicy = RetryPolicy::new(1, 0);
+
+    ///
+    /// Construct a new policy marking the displays out of sync on failure
+    ///
+    /// # Arguments
+    ///
+    /// * `attempts` - total number of attempts per transfer
+    /// * `backoff_us` - delay before the first retry in microseconds
+    ///
+    pub const fn new(attempts: u8, backoff_us: u32) -> Self {
+        RetryPolicy {
+            attempts,
+            backoff_us,
+            mark_dirty: true,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(3, 100)
+    }
+}
+
+/// Connector retrying failed transfers of another connector according to a [`RetryPolicy`].
+/// A retry resends the complete transfer, so registers latched by the
+/// failed attempt are written again.
+pub struct RetryConnector<CONNECTOR, DELAY>
+where
+    CONNECTOR: Connector,
+    DELAY: DelayNs,
+{
+    connector: CONNECTOR,
+    delay: DELAY,
+    policy: RetryPolicy,
+    dirty: bool,
+}
+
+impl<CONNECTOR, DELAY> RetryConnector<CONNECTOR, DELAY>
+where
+    CONNECTOR: Connector,
+    DELAY: DelayNs,
+{
+    pub(crate) fn new(connector: CONNECTOR, delay: DELAY, policy: RetryPolicy) -> Self {
+        RetryConnector {
+            connector,
+            delay,
+            policy,
+            dirty: false,
+        }
+    }
+
+    /// The policy applied to transfers
+    pub fn policy(&self) -> RetryPolicy {
+        self.policy
+    }
+
+    /// Releases the wrapped connector and delay provider
+    pub fn release(self) -> (CONNECTOR, DELAY) {
+        (self.connector, self.delay)
+    }
+
+    // runs a transfer until it succeeds or the attempts are used up
+    async fn retry<T>(
+        &mut self,
+        mut transfer: impl AsyncFnMut(&mut CONNECTOR) -> Result<T, DataError>,
+    ) -> Result<T, DataError> {
+        let mut backoff = self.policy.backoff_us;
+        let mut attempt = 1;
+
+        loop {
+            let result = transfer(&mut self.connector).await;
+            self.dirty |= self.connector.take_dirty();
+
+            match result {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    self.dirty |= self.policy.mark_dirty;
+                    if attempt >= self.policy.attempts {
+                        return Err(e);
+                    }
+                }
+            }
+
+            if backoff > 0 {
+                self.delay.delay_us(backoff).await;
+            }
+            backoff = backoff.saturating_mul(2);
+            attempt += 1;
+        }
+    }
+}
+
+impl<CONNECTOR, DELAY> Connector for RetryConnector<CONNECTOR, DELAY>
+where
+    CONNECTOR: Connector,
+    DELAY: DelayNs,
+{
+    async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+        self.retry(async |connector| connector.write_raw_bytes(bytes).await)
+            .await
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        core::mem::take(&mut self.dirty)
+    }
+}
+
+impl<CONNECTOR, DELAY> LoopbackConnector for RetryConnector<CONNECTOR, DELAY>
+where
+    CONNECTOR: LoopbackConnector,
+    DELAY: DelayNs,
+{
+    async fn transfer_raw_bytes(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), DataError> {
+        self.retry(async |connector| connector.transfer_raw_bytes(read, write).await)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        Event, Log, LogDelay, LogPin, LogSpi, Recorder, Waits, block_on, recorder,
+    };
+    use crate::{MAX_DIGITS, MAX7219};
+
+    extern crate std;
+    use std::vec::Vec;
+
+    // pin and delay events of bit banging one byte with given timing and idle levels
+    fn bit_banged(byte: u8, timing: PinTiming, clock: PinState, data: PinState) -> Vec<Event> {
+        let delay = |ns| (ns > 0).then_some(Event::Delay(ns));
+        let mut events = std::vec![Event::Pin("sck", clock == PinState::High)];
+        events.push(Event::Pin("cs", false));
+        events.extend(delay(timing.cs_setup_ns));
+        for i in 0..8 {
+            events.push(Event::Pin("sck", false));
+            events.push(Event::Pin("data", byte & (0x80 >> i) != 0));
+            events.extend(delay(timing.clock_low_ns));
+            events.push(Event::Pin("sck", true));
+            events.extend(delay(timing.clock_high_ns));
+        }
+        events.extend(delay(timing.cs_hold_ns));
+        events.push(Event::Pin("cs", true));
+        events.push(Event::Pin("sck", clock == PinState::High));
+        events.push(Event::Pin("data", data == PinState::High));
+        events.extend(delay(timing.load_pulse_ns));
+        events
+    }
+
+    fn pins(log: &Log) -> PinConnector<LogPin<'_>, LogPin<'_>, LogPin<'_>, LogDelay<'_>> {
+        PinConnector::new(
+            LogPin::new("data", log),
+            LogPin::new("cs", log),
+            LogPin::new("sck", log),
+        )
+        .with_timing(LogDelay(log), PinTiming::DATASHEET)
+    }
+
+    #[test]
+    fn bit_bangs_with_datasheet_timing() {
+        let log = Log::default();
+        let mut connector = pins(&log);
+
+        block_on(connector.write_raw_bytes(&[0xA5])).unwrap();
+
+        let events = log.take();
+        let expected = bit_banged(0xA5, PinTiming::DATASHEET, PinState::Low, PinState::Low);
+        assert_eq!(events, expected);
+        // cs setup, low and high per bit and the load pulse, no zero cs hold
+        let delays = events.iter().filter(|e| matches!(e, Event::Delay(_)));
+        assert_eq!(delays.count(), 1 + 8 * 2 + 1);
+    }
+
+    #[test]
+    fn bit_bangs_with_idle_levels() {
+        let log = Log::default();
+        let mut connector = pins(&log);
+        connector.set_idle_levels(PinState::High, PinState::High);
+
+        block_on(connector.write_raw_bytes(&[0x3C])).unwrap();
+
+        let expected = bit_banged(0x3C, PinTiming::DATASHEET, PinState::High, PinState::High);
+        assert_eq!(log.take(), expected);
+    }
+
+    #[test]
+    fn bit_bangs_without_delays() {
+        let log = Log::default();
+        let mut connector = pins(&log).with_timing(LogDelay(&log), PinTiming::NONE);
+
+        block_on(connector.write_raw_bytes(&[0xFF, 0x00])).unwrap();
+
+        let events = log.take();
+        assert!(!events.iter().any(|e| matches!(e, Event::Delay(_))));
+        let clocks = events.iter().filter(|e| **e == Event::Pin("sck", true));
+        assert_eq!(clocks.count(), 16);
+    }
+
+    fn retry(fail: usize, policy: RetryPolicy) -> RetryConnector<Recorder, Waits> {
+        let recorder = Recorder {
+            fail,
+            ..Recorder::default()
+        };
+        RetryConnector::new(recorder, Waits::default(), policy)
+    }
+
+    #[test]
+    fn latches_after_bus_flush() {
+        let log = Log::default();
+        let mut connector = SpiBusConnector::new(LogSpi::new(&log), LogPin::new("cs", &log));
+
+        block_on(connector.write_raw_bytes(&[0x0C, 0x01])).unwrap();
+        let mut read = [0xFF; 2];
+        block_on(connector.transfer_raw_bytes(&mut read, &[0x00, 0x00])).unwrap();
+
+        let transfer = |bytes: &[u8]| {
+            [
+                Event::Pin("cs", false),
+                Event::Write(bytes.to_vec()),
+                Event::Flush,
+                Event::Pin("cs", true),
+            ]
+        };
+        let expected: Vec<Event> = [transfer(&[0x0C, 0x01]), transfer(&[0x00, 0x00])].concat();
+        assert_eq!(log.take(), expected);
+        assert_eq!(read, [0x00, 0x00]);
+    }
+
+    #[test]
+    fn keeps_cs_low_on_bus_error() {
+        let log = Log::default();
+        let bus = LogSpi {
+            log: &log,
+            fail: true,
+        };
+        let mut connector = SpiBusConnector::new(bus, LogPin::new("cs", &log));
+
+        let result = block_on(connector.write_raw_bytes(&[0x0C, 0x01]));
+
+        assert!(matches!(result, Err(DataError::Spi)));
+        assert_eq!(log.take(), [Event::Pin("cs", false)]);
+    }
+
+    #[test]
+    fn writes_blocking_transaction() {
+        let log = Log::default();
+        let mut connector = BlockingSpiConnector::new(LogSpi::new(&log));
+
+        block_on(connector.write_raw_bytes(&[0x0C, 0x01, 0x0C, 0x01])).unwrap();
+
+        let expected = [
+            Event::Pin("cs", false),
+            Event::Write(std::vec![0x0C, 0x01, 0x0C, 0x01]),
+            Event::Flush,
+            Event::Pin("cs", true),
+        ];
+        assert_eq!(log.take(), expected);
+
+        connector.spi.fail = true;
+        let result = block_on(connector.write_raw_bytes(&[0x0C, 0x01]));
+        assert!(matches!(result, Err(DataError::Spi)));
+        assert!(log.take().is_empty());
+    }
+
+    type Chains<'a> = MultiChainConnector<LogSpi<'a>, LogPin<'a>, 2>;
+
+    fn chains(log: &Log) -> Chains<'_> {
+        MultiChainConnector::new(
+            LogSpi::new(log),
+            [LogPin::new("cs0", log), LogPin::new("cs1", log)],
+        )
+    }
+
+    // bytes written per chain in order, by the CS pin framing each write
+    fn chain_writes(events: &[Event]) -> Vec<(&'static str, Vec<u8>)> {
+        events
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [Event::Pin(cs, false), Event::Write(bytes)] => Some((*cs, bytes.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_transfers_per_chain() {
+        let log = Log::default();
+        let mut connector = chains(&log);
+
+        block_on(connector.write_raw_bytes(&[0x0A, 0x01, 0x0A, 0x02, 0x0A, 0x03, 0x0A, 0x04]))
+            .unwrap();
+
+        let expected = [
+            Event::Pin("cs0", false),
+            Event::Write(std::vec![0x0A, 0x01, 0x0A, 0x02]),
+            Event::Flush,
+            Event::Pin("cs0", true),
+            Event::Pin("cs1", false),
+            Event::Write(std::vec![0x0A, 0x03, 0x0A, 0x04]),
+            Event::Flush,
+            Event::Pin("cs1", true),
+        ];
+        assert_eq!(log.take(), expected);
+    }
+
+    #[test]
+    fn skips_chains_without_registers() {
+        let log = Log::default();
+        let mut display = MAX7219::<4, _>::from_spi_bus_chains(
+            LogSpi::new(&log),
+            [LogPin::new("cs0", &log), LogPin::new("cs1", &log)],
+        );
+
+        let addr = MAX7219::<4, Chains>::chain_addr(1, 1);
+        block_on(display.set_intensity(addr, 0x05)).unwrap();
+
+        assert_eq!(
+            chain_writes(&log.take()),
+            [("cs1", std::vec![0x00, 0x00, 0x0A, 0x05])]
+        );
+    }
+
+    #[test]
+    fn rejects_partial_transfers() {
+        let log = Log::default();
+        let mut connector = chains(&log);
+
+        for bytes in [&[0x0A, 0x01][..], &[0x0A, 0x01, 0x0A, 0x02, 0x0A, 0x03]] {
+            let result = block_on(connector.write_raw_bytes(bytes));
+            assert!(matches!(result, Err(DataError::Spi)));
+        }
+        assert!(log.take().is_empty());
+    }
+
+    #[test]
+    fn writes_frames_row_by_row_across_chains() {
+        let log = Log::default();
+        let mut display = MAX7219::<4, _>::from_spi_bus_chains(
+            LogSpi::new(&log),
+            [LogPin::new("cs0", &log), LogPin::new("cs1", &log)],
+        );
+
+        block_on(display.write_frame(&[[0x81; MAX_DIGITS]; 4])).unwrap();
+
+        let rows: Vec<(&str, u8)> = chain_writes(&log.take())
+            .into_iter()
+            .filter(|(_, bytes)| (0x01..=0x08).contains(&bytes[0]))
+            .map(|(cs, bytes)| (cs, bytes[0]))
+            .collect();
+        let expected: Vec<(&str, u8)> = (0x01..=0x08)
+            .flat_map(|row| [("cs0", row), ("cs1", row)])
+            .collect();
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn maps_chain_addresses() {
+        type Display<'a> = MAX7219<6, Chains<'a>>;
+
+        assert_eq!(Display::CHAIN_LEN, 3);
+        assert_eq!(Display::chain_addr(0, 2), 2);
+        assert_eq!(Display::chain_addr(1, 0), 3);
+        for addr in 0..6 {
+            let (chain, index) = Display::split_addr(addr);
+            assert_eq!(Display::chain_addr(chain, index), addr);
+        }
+        assert_eq!(Display::split_addr(5), (1, 2));
+    }
+
+    #[test]
+    fn retries_with_doubling_backoff() {
+        let mut connector = retry(2, RetryPolicy::new(3, 100));
+
+        block_on(connector.write_raw_bytes(&[0x0C, 0x01])).unwrap();
+
+        assert!(connector.take_dirty());
+        assert!(!connector.take_dirty());
+        let (recorder, waits) = connector.release();
+        assert_eq!(recorder.transfers, [[0x0C, 0x01]]);
+        assert_eq!(waits.0, [100_000, 200_000]);
+    }
+
+    #[test]
+    fn gives_up_after_attempts() {
+        let mut connector = retry(5, RetryPolicy::new(3, 0));
+
+        assert!(block_on(connector.write_raw_bytes(&[0x0C, 0x01])).is_err());
+
+        let (recorder, waits) = connector.release();
+        assert_eq!(recorder.fail, 2);
+        assert!(waits.0.is_empty());
+    }
+
+    #[test]
+    fn keeps_sync_without_mark_dirty() {
+        let policy = RetryPolicy {
+            mark_dirty: false,
+            ..RetryPolicy::default()
+        };
+        let mut connector = retry(1, policy);
+
+        block_on(connector.write_raw_bytes(&[0x0C, 0x01])).unwrap();
+
+        assert!(!connector.take_dirty());
+        assert!(block_on(retry(1, RetryPolicy::NONE).write_raw_bytes(&[0x0C, 0x01])).is_err());
+    }
+
+    #[test]
+    fn resyncs_after_retried_transfer() {
+        let mut display = recorder::<1>().with_retry(Waits::default(), RetryPolicy::default());
+        display.connector.connector.fail = 1;
+
+        block_on(display.set_power_all(true)).unwrap();
+        block_on(display.set_intensity_all(0x03)).unwrap();
+
+        let transfers = core::mem::take(&mut display.connector.connector.transfers);
+        assert_eq!(transfers.len(), 1 + crate::RESYNC_ORDER.len() + 1);
+        assert_eq!(transfers[0], [0x0C, 0x01]);
+        assert_eq!(transfers[transfers.len() - 1], [0x0A, 0x03]);
+    }
+}
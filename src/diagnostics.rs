@@ -0,0 +1,348 @@
+//! Test patterns for production line testing
+//!
+//! Each [`Pattern`] is shown as a sequence of raw frames on all displays,
+//! one step per delay. After each pattern an operator callback confirms
+//! whether it looked right. Patterns overwrite what the displays show and
+//! disable decoding. A complete test restores all registers afterwards, so the
+//! displays show what they did before, while a single pattern leaves its last
+//! step on the displays.
+
+use core::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::connectors::Connector;
+use crate::fade::{MAX_INTENSITY, delay_us};
+use crate::{DataError, MAX_DIGITS, MAX7219};
+
+///
+/// Test pattern shown by [`SelfTest`]
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pattern {
+    /// Lights each segment in turn, the same one on all displays
+    SegmentWalk,
+    /// Lights each pixel of the whole chain in turn, display by display
+    PixelWalk,
+    /// Display `n` lights its first `n % 8 + 1` digits (rows), then its first
+    /// `n / 8` segments (columns) of all digits, wrapping every 72 displays
+    ChainIndex,
+    /// Lights all segments and steps through every intensity from dim to bright,
+    /// adjusted by the intensity offset of each display
+    IntensitySteps,
+    /// Alternating segments followed by their inverse
+    Checkerboard,
+    /// Lights each digit (row) in turn on all displays
+    RowSweep,
+    /// Lights each segment (column) of all digits in turn on all displays
+    ColumnSweep,
+}
+
+impl Pattern {
+    /// All patterns in the order of a complete test
+    pub const ALL: [Pattern; 7] = [
+        Pattern::ChainIndex,
+        Pattern::SegmentWalk,
+        Pattern::PixelWalk,
+        Pattern::RowSweep,
+        Pattern::ColumnSweep,
+        Pattern::Checkerboard,
+        Pattern::IntensitySteps,
+    ];
+}
+
+///
+/// Runs test patterns with a fixed time per step
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SelfTest {
+    step: Duration,
+}
+
+impl SelfTest {
+    ///
+    /// Construct a new self-test
+    ///
+    /// # Arguments
+    ///
+    /// * `step` - time each step of a pattern is shown
+    ///
+    pub const fn new(step: Duration) -> Self {
+        SelfTest { step }
+    }
+
+    /// Time each step of a pattern is shown
+    pub const fn step(&self) -> Duration {
+        self.step
+    }
+
+    ///
+    /// Shows patterns in order, asking the operator to confirm each one after
+    /// its last step. Stops at the first pattern not confirmed and returns it,
+    /// `None` if all were confirmed. All registers are rewritten to what they
+    /// were before the test afterwards, also when a transfer failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to test
+    /// * `delay` - delay provider used to time the steps
+    /// * `patterns` - the patterns to show, for example `Pattern::ALL`
+    /// * `confirm` - operator callback returning whether the pattern looked right
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn run<const D: usize, CONNECTOR, DELAY>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        delay: &mut DELAY,
+        patterns: &[Pattern],
+        mut confirm: impl AsyncFnMut(Pattern) -> bool,
+    ) -> Result<Option<Pattern>, DataError>
+    where
+        CONNECTOR: Connector,
+        DELAY: DelayNs,
+    {
+        let registers = display.registers;
+        let result = self.show_all(display, delay, patterns, &mut confirm).await;
+
+        display.registers = registers;
+        let restored = display.resync().await;
+
+        let failed = result?;
+        restored?;
+
+        Ok(failed)
+    }
+
+    ///
+    /// Shows all steps of a single pattern, the last one is left on the displays
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to test
+    /// * `delay` - delay provider used to time the steps
+    /// * `pattern` - the pattern to show
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn show<const D: usize, CONNECTOR, DELAY>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        delay: &mut DELAY,
+        pattern: Pattern,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+        DELAY: DelayNs,
+    {
+        match pattern {
+            Pattern::SegmentWalk => {
+                for (row, bit) in segments() {
+                    let mut frame = [[0x00; MAX_DIGITS]; D];
+                    for raw in frame.iter_mut() {
+                        raw[row] = 0x80 >> bit;
+                    }
+                    self.show_frame(display, delay, &frame).await?;
+                }
+            }
+            Pattern::PixelWalk => {
+                for addr in 0..D {
+                    for (row, bit) in segments() {
+                        let mut frame = [[0x00; MAX_DIGITS]; D];
+                        frame[addr][row] = 0x80 >> bit;
+                        self.show_frame(display, delay, &frame).await?;
+                    }
+                }
+            }
+            Pattern::ChainIndex => {
+                let rows = core::array::from_fn(|addr| {
+                    core::array::from_fn(|row| if row <= addr % MAX_DIGITS { 0xFF } else { 0x00 })
+                });
+                self.show_frame(display, delay, &rows).await?;
+
+                let columns = core::array::from_fn(|addr| {
+                    let count = (addr / MAX_DIGITS % (MAX_DIGITS + 1)) as u32;
+                    [!0xFFu8.checked_shr(count).unwrap_or(0); MAX_DIGITS]
+                });
+                self.show_frame(display, delay, &columns).await?;
+            }
+            Pattern::IntensitySteps => {
+                let intensities = core::array::from_fn(|addr| display.intensity(addr));
+                let result = self.sweep_intensity(display, delay).await;
+                let restored = display.restore_intensities(&intensities).await;
+
+                result?;
+                restored?;
+            }
+            Pattern::Checkerboard => {
+                for even in [0xAA, 0x55] {
+                    let frame =
+                        [core::array::from_fn(|row| if row % 2 == 0 { even } else { !even }); D];
+                    self.show_frame(display, delay, &frame).await?;
+                }
+            }
+            Pattern::RowSweep => {
+                for row in 0..MAX_DIGITS {
+                    let mut frame = [[0x00; MAX_DIGITS]; D];
+                    for raw in frame.iter_mut() {
+                        raw[row] = 0xFF;
+                    }
+                    self.show_frame(display, delay, &frame).await?;
+                }
+            }
+            Pattern::ColumnSweep => {
+                for bit in 0..8 {
+                    let frame = [[0x80 >> bit; MAX_DIGITS]; D];
+                    self.show_frame(display, delay, &frame).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn show_all<const D: usize, CONNECTOR, DELAY>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        delay: &mut DELAY,
+        patterns: &[Pattern],
+        confirm: &mut impl AsyncFnMut(Pattern) -> bool,
+    ) -> Result<Option<Pattern>, DataError>
+    where
+        CONNECTOR: Connector,
+        DELAY: DelayNs,
+    {
+        for pattern in patterns {
+            self.show(display, delay, *pattern).await?;
+            if !confirm(*pattern).await {
+                return Ok(Some(*pattern));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // lights all segments and shows each intensity for one step
+    async fn sweep_intensity<const D: usize, CONNECTOR, DELAY>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        delay: &mut DELAY,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+        DELAY: DelayNs,
+    {
+        display.write_frame(&[[0xFF; MAX_DIGITS]; D]).await?;
+        for intensity in 0..=MAX_INTENSITY {
+            display.set_intensity_all(intensity).await?;
+            delay_us(delay, self.step.as_micros() as u64).await;
+        }
+
+        Ok(())
+    }
+
+    // writes a frame and shows it for one step
+    async fn show_frame<const D: usize, CONNECTOR, DELAY>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        delay: &mut DELAY,
+        frame: &[[u8; MAX_DIGITS]; D],
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+        DELAY: DelayNs,
+    {
+        display.write_frame(frame).await?;
+        delay_us(delay, self.step.as_micros() as u64).await;
+
+        Ok(())
+    }
+}
+
+// every segment as (row, bit from the most significant one)
+fn segments() -> impl Iterator<Item = (usize, u8)> {
+    (0..MAX_DIGITS).flat_map(|row| (0..8).map(move |bit| (row, bit)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::connectors::NoDelay;
+    use crate::testing::{Recorder, block_on, recorder, take};
+
+    const TEST: SelfTest = SelfTest::new(Duration::ZERO);
+
+    fn prepared<const D: usize>() -> MAX7219<D, Recorder> {
+        let mut display = recorder::<D>();
+        block_on(async {
+            display.power_on().await.unwrap();
+            display.set_intensity_all(4).await.unwrap();
+            display.set_decode_mask_all(0xFF).await.unwrap();
+        });
+        take(&mut display);
+        display
+    }
+
+    #[test]
+    fn restores_registers_after_test() {
+        let mut display = prepared::<2>();
+        let registers = display.registers;
+
+        let mut delay = NoDelay;
+        let failed = block_on(TEST.run(&mut display, &mut delay, &Pattern::ALL, async |_| true));
+
+        assert_eq!(failed.unwrap(), None);
+        assert_eq!(display.registers, registers);
+        let transfers = take(&mut display);
+        let power = Command::Power as u8;
+        assert_eq!(transfers.last().unwrap(), &[power, 1, power, 1]);
+    }
+
+    #[test]
+    fn restores_registers_after_failure() {
+        let mut display = prepared::<2>();
+        let registers = display.registers;
+        display.connector.pass = 20;
+        display.connector.fail = 1;
+
+        let mut delay = NoDelay;
+        let failed = block_on(TEST.run(&mut display, &mut delay, &Pattern::ALL, async |_| true));
+
+        assert!(failed.is_err());
+        assert_eq!(display.registers, registers);
+    }
+
+    #[test]
+    fn sweeps_calibrated_intensities() {
+        let mut display = prepared::<2>();
+        display.set_intensity_offsets([0, 3]);
+        display.connector.pass = 12;
+        display.connector.fail = 1;
+
+        let mut delay = NoDelay;
+        let result = block_on(TEST.show(&mut display, &mut delay, Pattern::IntensitySteps));
+
+        assert!(result.is_err());
+        let intensity = Command::Intensity as u8;
+        let transfers = take(&mut display);
+        assert!(transfers.iter().any(|t| *t == [intensity, 0, intensity, 3]));
+        assert_eq!(transfers.last().unwrap(), &[intensity, 4, intensity, 4]);
+        assert_eq!((display.intensity(0), display.intensity(1)), (4, 4));
+    }
+
+    #[test]
+    fn identifies_long_chains() {
+        let mut display = recorder::<10>();
+
+        let mut delay = NoDelay;
+        block_on(TEST.show(&mut display, &mut delay, Pattern::ChainIndex)).unwrap();
+
+        assert_eq!(display.registers[0].digits, [0x00; MAX_DIGITS]);
+        assert_eq!(display.registers[9].digits, [0x80; MAX_DIGITS]);
+    }
+}
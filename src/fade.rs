@@ -0,0 +1,292 @@
+//! Intensity fading and brightness animation
+
+use core::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::connectors::Connector;
+use crate::{Command, DataError, MAX7219, Target};
+
+/// Highest intensity register value
+pub const MAX_INTENSITY: u8 = 0x0F;
+
+///
+/// Perceived brightness in 1/1000 of each intensity value. Intensity `n`
+/// drives the LEDs with a duty cycle of `(2n + 1) / 32` which is perceived
+/// roughly as its 2.2 power root.
+///
+const PERCEIVED: [u16; 16] = [
+    207, 341, 430, 501, 562, 615, 664, 709, 750, 789, 826, 861, 894, 926, 956, 986,
+];
+
+impl<const D: usize, CONNECTOR> MAX7219<D, CONNECTOR>
+where
+    CONNECTOR: Connector,
+{
+    ///
+    /// Sets intensity level on all displays in a single transfer,
+    /// adjusted by the intensity offset of each display
+    ///
+    /// # Arguments
+    ///
+    /// * `intensity` - intensity value to set to `0x00` to 0x0F`
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn set_intensity_all(&mut self, intensity: u8) -> Result<(), DataError> {
+        let mut buffers = [[0; 2]; D];
+        for (addr, buffer) in buffers.iter_mut().enumerate() {
+            *buffer = [
+                Command::Intensity as u8,
+                self.calibrated_intensity(addr, intensity),
+            ];
+        }
+
+        self.write_raw_bytes(buffers.as_flattened()).await
+    }
+
+    ///
+    /// Returns the intensity last set on given display
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - display to address as connected in series (0 -> last)
+    ///
+    pub fn intensity(&self, addr: usize) -> u8 {
+        self.registers[addr].intensity
+    }
+
+    ///
+    /// Fades intensity to target value over given duration. Steps are timed
+    /// so perceived brightness changes linearly and adjusted by the intensity
+    /// offset of each faded display. Chain wide fades start at the intensity
+    /// of display 0 and update all displays in a single transfer per step.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - display(s) to fade
+    /// * `intensity` - intensity value to end at `0x00` to 0x0F`
+    /// * `duration` - time the whole fade takes
+    /// * `delay` - delay provider used to time the steps
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn fade_to<DELAY>(
+        &mut self,
+        target: Target,
+        intensity: u8,
+        duration: Duration,
+        delay: &mut DELAY,
+    ) -> Result<(), DataError>
+    where
+        DELAY: DelayNs,
+    {
+        let intensity = intensity.min(MAX_INTENSITY);
+        let start = self.target_intensity(target);
+
+        let from = PERCEIVED[start as usize] as u64;
+        let to = PERCEIVED[intensity as usize] as u64;
+        let span = from.abs_diff(to).max(1);
+        let total_us = duration.as_micros() as u64;
+
+        let mut elapsed_us = 0;
+        let mut level = start;
+        while level != intensity {
+            level = if level < intensity {
+                level + 1
+            } else {
+                level - 1
+            };
+
+            // time at which perceived brightness reaches this level
+            let at_us = total_us * (PERCEIVED[level as usize] as u64).abs_diff(from) / span;
+            delay_us(delay, at_us - elapsed_us).await;
+            elapsed_us = at_us;
+
+            self.set_target_intensity(target, level).await?;
+        }
+
+        delay_us(delay, total_us - elapsed_us).await;
+
+        Ok(())
+    }
+
+    ///
+    /// Breathes intensity between two values, fading up and back down each cycle
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - display(s) to breathe
+    /// * `low` - intensity at the bottom of each breath
+    /// * `high` - intensity at the top of each breath
+    /// * `period` - time one full breath takes
+    /// * `cycles` - number of breaths
+    /// * `delay` - delay provider used to time the steps
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn breathe<DELAY>(
+        &mut self,
+        target: Target,
+        low: u8,
+        high: u8,
+        period: Duration,
+        cycles: usize,
+        delay: &mut DELAY,
+    ) -> Result<(), DataError>
+    where
+        DELAY: DelayNs,
+    {
+        let half = period / 2;
+        for _ in 0..cycles {
+            self.fade_to(target, high, half, delay).await?;
+            self.fade_to(target, low, half, delay).await?;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Pulses intensity up to a peak and back to the current value once
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - display(s) to pulse
+    /// * `peak` - intensity at the top of the pulse
+    /// * `duration` - time the whole pulse takes
+    /// * `delay` - delay provider used to time the steps
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn pulse<DELAY>(
+        &mut self,
+        target: Target,
+        peak: u8,
+        duration: Duration,
+        delay: &mut DELAY,
+    ) -> Result<(), DataError>
+    where
+        DELAY: DelayNs,
+    {
+        let current = self.target_intensity(target);
+
+        self.breathe(target, current, peak, duration, 1, delay)
+            .await
+    }
+
+    // current intensity of target without its offset, display 0 standing in for the whole chain
+    pub(crate) fn target_intensity(&self, target: Target) -> u8 {
+        let addr = match target {
+            Target::Display(addr) => addr,
+            Target::All => 0,
+        };
+        let intensity = self.registers[addr].intensity as i16 - self.intensity_offsets[addr] as i16;
+
+        intensity.clamp(0, MAX_INTENSITY as i16) as u8
+    }
+
+    // writes the raw intensity of each display in a single transfer unless all are set already
+    pub(crate) async fn restore_intensities(
+        &mut self,
+        intensities: &[u8; D],
+    ) -> Result<(), DataError> {
+        if (0..D).all(|addr| self.registers[addr].intensity == intensities[addr]) {
+            return Ok(());
+        }
+
+        let mut buffers = [[0; 2]; D];
+        for (buffer, intensity) in buffers.iter_mut().zip(intensities) {
+            *buffer = [Command::Intensity as u8, *intensity];
+        }
+
+        self.write_raw_bytes(buffers.as_flattened()).await
+    }
+
+    async fn set_target_intensity(
+        &mut self,
+        target: Target,
+        intensity: u8,
+    ) -> Result<(), DataError> {
+        match target {
+            Target::Display(addr) => {
+                let intensity = self.calibrated_intensity(addr, intensity);
+                self.set_intensity(addr, intensity).await
+            }
+            Target::All => self.set_intensity_all(intensity).await,
+        }
+    }
+}
+
+// delays for given microseconds, splitting values that do not fit into u32
+pub(crate) async fn delay_us<DELAY: DelayNs>(delay: &mut DELAY, mut us: u64) {
+    while us > 0 {
+        let chunk = us.min(u32::MAX as u64);
+        delay.delay_us(chunk as u32).await;
+        us -= chunk;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Recorder, Waits, block_on, recorder, take};
+
+    extern crate std;
+    use std::vec::Vec;
+
+    // intensities written to display 0, one per transfer
+    fn steps(display: &mut MAX7219<1, Recorder>) -> Vec<u8> {
+        take(display).iter().map(|t| t[1]).collect()
+    }
+
+    #[test]
+    fn fades_through_every_level() {
+        let mut display = recorder::<1>();
+        let mut delay = Waits::default();
+
+        block_on(display.fade_to(Target::All, 0x0F, Duration::from_secs(1), &mut delay)).unwrap();
+
+        assert_eq!(steps(&mut display), (1..=15).collect::<Vec<_>>());
+        assert_eq!(delay.total(), Duration::from_secs(1));
+        // steps between the lowest levels are the largest in perceived brightness and take longest
+        assert_eq!(delay.0.len(), 15);
+        assert!(delay.0[0] > delay.0[14]);
+    }
+
+    #[test]
+    fn breathes_and_pulses() {
+        let mut display = recorder::<1>();
+        let mut delay = Waits::default();
+        block_on(display.set_intensity_all(2)).unwrap();
+        take(&mut display);
+
+        let period = Duration::from_millis(100);
+        block_on(display.breathe(Target::All, 2, 4, period, 2, &mut delay)).unwrap();
+        assert_eq!(steps(&mut display), [3, 4, 3, 2, 3, 4, 3, 2]);
+        assert_eq!(delay.total(), 2 * period);
+
+        block_on(display.pulse(Target::Display(0), 5, period, &mut delay)).unwrap();
+        assert_eq!(steps(&mut display), [3, 4, 5, 4, 3, 2]);
+    }
+
+    #[test]
+    fn fades_single_display_with_offset() {
+        let mut display = recorder::<2>();
+        display.set_intensity_offsets([0, 2]);
+        let mut delay = Waits::default();
+
+        block_on(display.fade_to(Target::Display(1), 3, Duration::ZERO, &mut delay)).unwrap();
+
+        assert_eq!(take(&mut display).len(), 3);
+        assert_eq!((display.intensity(0), display.intensity(1)), (0, 5));
+        assert_eq!(display.target_intensity(Target::Display(1)), 3);
+    }
+}
@@ -0,0 +1,213 @@
+//! Font tables for matrix text and seven segment digits
+//!
+//! Tables are sorted by code point so glyphs are found by binary search.
+//! Fonts can be generated from BDF and PSF files with `max7219-fontgen`.
+
+use crate::MAX_DIGITS;
+use crate::framebuffer::FrameBuffer;
+
+///
+/// A glyph of a matrix font, up to 8 pixels wide and high.
+/// Columns are stored left to right with the top row in bit 0.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Glyph {
+    /// Character shown by this glyph
+    pub codepoint: char,
+    /// Number of columns used, also the advance to the next glyph
+    pub width: u8,
+    /// Pixel columns, the top row in bit 0
+    pub columns: [u8; MAX_DIGITS],
+}
+
+impl Glyph {
+    ///
+    /// Construct a new glyph
+    ///
+    /// # Arguments
+    ///
+    /// * `codepoint` - character shown by this glyph
+    /// * `width` - number of columns used, `0` to `8`
+    /// * `columns` - pixel columns, the top row in bit 0
+    ///
+    pub const fn new(codepoint: char, width: u8, columns: [u8; MAX_DIGITS]) -> Self {
+        assert!(width as usize <= MAX_DIGITS, "glyph wider than 8 pixels");
+
+        Glyph {
+            codepoint,
+            width,
+            columns,
+        }
+    }
+}
+
+///
+/// Proportional font for text on matrix displays
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MatrixFont<'a> {
+    glyphs: &'a [Glyph],
+    spacing: u8,
+}
+
+impl<'a> MatrixFont<'a> {
+    ///
+    /// Construct a new font with one blank column between glyphs
+    ///
+    /// # Panics
+    ///
+    /// * if the glyphs are not sorted by code point without duplicates
+    ///
+    pub const fn new(glyphs: &'a [Glyph]) -> Self {
+        let mut i = 1;
+        while i < glyphs.len() {
+            assert!(
+                (glyphs[i - 1].codepoint as u32) < glyphs[i].codepoint as u32,
+                "glyphs must be sorted by code point"
+            );
+            i += 1;
+        }
+
+        MatrixFont { glyphs, spacing: 1 }
+    }
+
+    ///
+    /// Changes the number of blank columns between glyphs
+    ///
+    pub const fn with_spacing(mut self, spacing: u8) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// All glyphs of the font
+    pub const fn glyphs(&self) -> &'a [Glyph] {
+        self.glyphs
+    }
+
+    ///
+    /// Returns the glyph for a character, falling back to `?` if present
+    ///
+    pub fn glyph(&self, c: char) -> Option<&'a Glyph> {
+        self.find(c).or_else(|| self.find('?'))
+    }
+
+    ///
+    /// Returns the width of a text in pixels including spacing
+    ///
+    pub fn text_width(&self, text: &str) -> usize {
+        text.chars()
+            .filter_map(|c| self.glyph(c))
+            .map(|glyph| glyph.width as usize + self.spacing as usize)
+            .sum::<usize>()
+            .saturating_sub(self.spacing as usize)
+    }
+
+    fn find(&self, c: char) -> Option<&'a Glyph> {
+        self.glyphs
+            .binary_search_by_key(&c, |glyph| glyph.codepoint)
+            .ok()
+            .map(|index| &self.glyphs[index])
+    }
+}
+
+///
+/// Character to segment byte table for seven segment digits
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SegmentFont<'a> {
+    glyphs: &'a [(char, u8)],
+}
+
+impl<'a> SegmentFont<'a> {
+    ///
+    /// Construct a new segment font
+    ///
+    /// # Panics
+    ///
+    /// * if the glyphs are not sorted by code point without duplicates
+    ///
+    pub const fn new(glyphs: &'a [(char, u8)]) -> Self {
+        let mut i = 1;
+        while i < glyphs.len() {
+            assert!(
+                (glyphs[i - 1].0 as u32) < glyphs[i].0 as u32,
+                "glyphs must be sorted by code point"
+            );
+            i += 1;
+        }
+
+        SegmentFont { glyphs }
+    }
+
+    /// All glyphs of the font
+    pub const fn glyphs(&self) -> &'a [(char, u8)] {
+        self.glyphs
+    }
+
+    ///
+    /// Returns the segment byte for a character
+    ///
+    pub fn segments(&self, c: char) -> Option<u8> {
+        self.glyphs
+            .binary_search_by_key(&c, |(codepoint, _)| *codepoint)
+            .ok()
+            .map(|index| self.glyphs[index].1)
+    }
+}
+
+impl<const D: usize> FrameBuffer<D> {
+    ///
+    /// Draws text with its top left corner at given position, only lit
+    /// pixels are drawn. Characters missing from the font are drawn as `?`
+    /// if the font has one and skipped otherwise.
+    /// Returns the column following the text.
+    ///
+    /// # Arguments
+    ///
+    /// * `font` - the font to draw with
+    /// * `text` - the text to draw
+    /// * `x` - column of the left text edge, may be negative for scrolling
+    /// * `y` - row of the top text edge, may be negative
+    ///
+    pub fn draw_text(&mut self, font: &MatrixFont, text: &str, x: i32, y: i32) -> i32 {
+        let mut x = x;
+        for glyph in text.chars().filter_map(|c| font.glyph(c)) {
+            for (gx, column) in glyph.columns[..glyph.width as usize].iter().enumerate() {
+                for gy in 0..MAX_DIGITS {
+                    let (fx, fy) = (x + gx as i32, y + gy as i32);
+                    if column & (1 << gy) != 0 && fx >= 0 && fy >= 0 {
+                        self.set_pixel(fx as usize, fy as usize, true);
+                    }
+                }
+            }
+            x += glyph.width as i32 + font.spacing as i32;
+        }
+
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLYPHS: [Glyph; 2] = [
+        Glyph::new('A', 8, [0xFF; MAX_DIGITS]),
+        Glyph::new('B', 8, [0xFF; MAX_DIGITS]),
+    ];
+
+    #[test]
+    fn measures_wide_spacing() {
+        let font = MatrixFont::new(&GLYPHS).with_spacing(u8::MAX);
+
+        assert_eq!(font.text_width("AB"), 8 + 255 + 8);
+    }
+
+    #[test]
+    fn draws_with_wide_spacing() {
+        let font = MatrixFont::new(&GLYPHS).with_spacing(u8::MAX);
+        let mut framebuffer = FrameBuffer::<1>::new();
+
+        assert_eq!(framebuffer.draw_text(&font, "AB", 0, 0), 2 * (8 + 255));
+    }
+}
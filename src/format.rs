@@ -0,0 +1,304 @@
+//! Number formatting for seven segment displays
+
+use crate::MAX_DIGITS;
+
+/// Widest field rendered, enough for any `i64` in binary
+pub const MAX_WIDTH: usize = 64;
+
+/// Numeric base used when rendering a number
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Radix {
+    Binary = 2,
+    Octal = 8,
+    Decimal = 10,
+    Hex = 16,
+}
+
+/// Placement of the rendered number inside its field
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// When to show the sign of a number
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sign {
+    /// Only negative numbers get a `-`
+    Negative,
+    /// Positive numbers and zero get an explicit `+`
+    Always,
+}
+
+/// What to show when a number does not fit into its field
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Overflow {
+    /// Show `Err`
+    Error,
+    /// Show the largest (or smallest) value that fits
+    Saturate,
+    /// Fill the field with `-`
+    Dashes,
+    /// Show the lowest digits that fit, dropping the leading ones
+    LowDigits,
+}
+
+///
+/// Describes how a number is rendered into a field of digits.
+///
+/// The field is `width` digits wide and can be placed anywhere on a device,
+/// so two or more numbers can share one display.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NumberFormat {
+    /// Number of digits the field occupies, at most [`MAX_WIDTH`]
+    pub width: usize,
+    /// Base used to render the number
+    pub radix: Radix,
+    /// Placement of the number when it is shorter than the field
+    pub align: Align,
+    /// Fill the field with leading zeros between the sign and the number
+    pub zero_pad: bool,
+    /// When to show the sign
+    pub sign: Sign,
+    /// What to show when the number does not fit
+    pub overflow: Overflow,
+}
+
+impl NumberFormat {
+    ///
+    /// Right justified, full width decimal format
+    ///
+    pub const fn decimal() -> Self {
+        Self::new(Radix::Decimal)
+    }
+
+    ///
+    /// Right justified, full width hexadecimal format
+    ///
+    pub const fn hex() -> Self {
+        Self::new(Radix::Hex)
+    }
+
+    ///
+    /// Right justified, full width octal format
+    ///
+    pub const fn octal() -> Self {
+        Self::new(Radix::Octal)
+    }
+
+    ///
+    /// Right justified, full width binary format
+    ///
+    pub const fn binary() -> Self {
+        Self::new(Radix::Binary)
+    }
+
+    ///
+    /// Right justified, full width format in given radix
+    ///
+    /// # Arguments
+    ///
+    /// * `radix` - the base used to render numbers
+    ///
+    pub const fn new(radix: Radix) -> Self {
+        NumberFormat {
+            width: MAX_DIGITS,
+            radix,
+            align: Align::Right,
+            zero_pad: false,
+            sign: Sign::Negative,
+            overflow: Overflow::Error,
+        }
+    }
+
+    ///
+    /// Renders value as ASCII bytes into the beginning of `out`.
+    /// Exactly `width` bytes are written, or less if `out` is shorter
+    /// or `width` exceeds [`MAX_WIDTH`].
+    /// Returns the number of bytes written.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - the number to render
+    /// * `out` - buffer receiving the ASCII bytes, leftmost digit first
+    ///
+    pub fn format(&self, value: i64, out: &mut [u8]) -> usize {
+        let width = self.width.min(out.len()).min(MAX_WIDTH);
+        let out = &mut out[..width];
+        let negative = value < 0;
+        let sign = match (negative, self.sign) {
+            (true, _) => Some(b'-'),
+            (false, Sign::Always) => Some(b'+'),
+            (false, Sign::Negative) => None,
+        };
+        let sign_len = sign.is_some() as usize;
+
+        // least significant digit first, u64 holds 64 binary digits
+        let mut digits = [0u8; 64];
+        let mut len = radix_bytes(value.unsigned_abs(), self.radix, &mut digits);
+
+        if len + sign_len > width {
+            match self.overflow {
+                Overflow::Error => return self.fill_aligned(b"Err", out),
+                Overflow::Dashes => {
+                    out.fill(b'-');
+                    return width;
+                }
+                Overflow::Saturate => {
+                    // largest magnitude that fits is all highest digits, e.g. 9999 or -999
+                    let max = match self.radix {
+                        Radix::Hex => b'f',
+                        radix => b'0' + radix as u8 - 1,
+                    };
+                    len = width.saturating_sub(sign_len);
+                    digits[..len].fill(max);
+                }
+                Overflow::LowDigits => len = width.saturating_sub(sign_len),
+            }
+        }
+
+        let mut field = [b' '; MAX_WIDTH];
+        let mut pos = 0;
+        if let Some(s) = sign {
+            field[pos] = s;
+            pos += 1;
+        }
+        if self.zero_pad {
+            let zeros = width.saturating_sub(len + sign_len);
+            field[pos..pos + zeros].fill(b'0');
+            pos += zeros;
+        }
+        for d in digits[..len].iter().rev() {
+            field[pos] = *d;
+            pos += 1;
+        }
+
+        self.fill_aligned(&field[..pos.min(width)], out)
+    }
+
+    // copies bytes into out according to alignment, blanking the rest
+    fn fill_aligned(&self, bytes: &[u8], out: &mut [u8]) -> usize {
+        let len = bytes.len().min(out.len());
+        let start = match self.align {
+            Align::Left => 0,
+            Align::Right => out.len() - len,
+        };
+        out.fill(b' ');
+        out[start..start + len].copy_from_slice(&bytes[..len]);
+        out.len()
+    }
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self::decimal()
+    }
+}
+
+///
+/// Convert the absolute value of an integer into ASCII digits
+/// in given radix, least significant digit first
+///
+fn radix_bytes(mut n: u64, radix: Radix, buf: &mut [u8]) -> usize {
+    let base = radix as u64;
+    let mut i = 0;
+    loop {
+        let digit = (n % base) as u8;
+        buf[i] = match digit {
+            0..=9 => b'0' + digit,
+            _ => b'a' + digit - 10,
+        };
+        n /= base;
+        i += 1;
+        if n == 0 {
+            return i;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: NumberFormat, value: i64) -> [u8; MAX_DIGITS] {
+        let mut out = [b'?'; MAX_DIGITS];
+        let len = format.format(value, &mut out);
+        assert_eq!(len, format.width.min(MAX_DIGITS));
+        out
+    }
+
+    fn with_width(width: usize) -> NumberFormat {
+        NumberFormat {
+            width,
+            ..NumberFormat::decimal()
+        }
+    }
+
+    #[test]
+    fn aligns_and_signs() {
+        assert_eq!(&render(NumberFormat::decimal(), 42), b"      42");
+        assert_eq!(&render(NumberFormat::decimal(), -42), b"     -42");
+        assert_eq!(&render(NumberFormat::hex(), 0xbeef), b"    beef");
+
+        let format = NumberFormat {
+            align: Align::Left,
+            sign: Sign::Always,
+            ..with_width(4)
+        };
+        assert_eq!(&render(format, 7)[..4], b"+7  ");
+    }
+
+    #[test]
+    fn zero_pads_after_sign() {
+        let format = NumberFormat {
+            zero_pad: true,
+            ..with_width(5)
+        };
+        assert_eq!(&render(format, -12)[..5], b"-0012");
+        assert_eq!(&render(format, 12)[..5], b"00012");
+    }
+
+    #[test]
+    fn handles_overflow() {
+        let format = |overflow| NumberFormat {
+            overflow,
+            ..with_width(3)
+        };
+        assert_eq!(&render(format(Overflow::Error), 1234)[..3], b"Err");
+        assert_eq!(&render(format(Overflow::Dashes), 1234)[..3], b"---");
+        assert_eq!(&render(format(Overflow::Saturate), 1234)[..3], b"999");
+        assert_eq!(&render(format(Overflow::Saturate), -1234)[..3], b"-99");
+        assert_eq!(&render(format(Overflow::LowDigits), 1234)[..3], b"234");
+    }
+
+    #[test]
+    fn renders_extremes() {
+        let mut out = [0; MAX_WIDTH];
+        let binary = NumberFormat {
+            width: MAX_WIDTH,
+            ..NumberFormat::binary()
+        };
+        assert_eq!(binary.format(i64::MAX, &mut out), MAX_WIDTH);
+        assert_eq!(out[0], b' ');
+        assert!(out[1..].iter().all(|b| *b == b'1'));
+
+        // 64 binary digits and the sign do not fit
+        binary.format(i64::MIN, &mut out);
+        assert_eq!(&out[MAX_WIDTH - 3..], b"Err");
+    }
+
+    #[test]
+    fn clamps_wide_fields() {
+        let mut out = [0; 100];
+        let format = NumberFormat {
+            zero_pad: true,
+            overflow: Overflow::Saturate,
+            ..with_width(100)
+        };
+        assert_eq!(format.format(1, &mut out), MAX_WIDTH);
+        assert_eq!(out[MAX_WIDTH - 1], b'1');
+        assert!(out[..MAX_WIDTH - 1].iter().all(|b| *b == b'0'));
+    }
+}
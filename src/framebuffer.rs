@@ -0,0 +1,356 @@
+//! Frame buffer for chained 8x8 matrix displays
+//!
+//! Pixels are addressed across the whole chain, column `x` running from bit 7
+//! of display 0 to bit 0 of display `D - 1` and row `y` being digit `y`.
+//! Rows changed since the last flush are tracked so only those are sent.
+//!
+//! Modules mounted rotated are handled by setting a [`Rotation`], pixel
+//! coordinates are then mapped onto each module accordingly. Raw frames
+//! as used by `write_frame` are never rotated.
+
+use crate::animation::Sprite;
+use crate::connectors::Connector;
+use crate::{DataError, DecodeMode, MAX_DIGITS, MAX7219};
+
+/// Clockwise rotation of the content of each 8x8 module
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rotation {
+    None,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    ///
+    /// Maps a pixel position within a module to its rotated position
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - column within the module `0` to `7`
+    /// * `y` - row within the module `0` to `7`
+    ///
+    pub const fn apply(self, x: usize, y: usize) -> (usize, usize) {
+        match self {
+            Rotation::None => (x, y),
+            Rotation::Deg90 => (7 - y, x),
+            Rotation::Deg180 => (7 - x, 7 - y),
+            Rotation::Deg270 => (y, 7 - x),
+        }
+    }
+
+    ///
+    /// Returns whether a pixel is lit in raw display bytes as used by `write_frame`,
+    /// out of range pixels are dark
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - raw bytes per display as connected in series (0 -> last)
+    /// * `x` - column across the whole chain
+    /// * `y` - row `0` to `7`
+    ///
+    pub fn pixel<const D: usize>(self, frame: &[[u8; MAX_DIGITS]; D], x: usize, y: usize) -> bool {
+        if x >= D * 8 || y >= MAX_DIGITS {
+            return false;
+        }
+
+        let (column, row) = self.apply(x % 8, y);
+        frame[x / 8][row] & (0b1000_0000 >> column) != 0
+    }
+}
+
+///
+/// Frame buffer over all chained matrix displays
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FrameBuffer<const D: usize> {
+    frame: [[u8; MAX_DIGITS]; D],
+    dirty: u8,
+    rotation: Rotation,
+}
+
+impl<const D: usize> FrameBuffer<D> {
+    ///
+    /// Construct a new blank frame buffer. All rows start dirty
+    /// so the first flush overwrites whatever the displays show.
+    ///
+    pub const fn new() -> Self {
+        FrameBuffer {
+            frame: [[0x00; MAX_DIGITS]; D],
+            dirty: 0xFF,
+            rotation: Rotation::None,
+        }
+    }
+
+    ///
+    /// Construct a new blank frame buffer for rotated modules
+    ///
+    /// # Arguments
+    ///
+    /// * `rotation` - rotation applied to the content of each module
+    ///
+    pub const fn with_rotation(rotation: Rotation) -> Self {
+        let mut framebuffer = Self::new();
+        framebuffer.rotation = rotation;
+        framebuffer
+    }
+
+    /// Rotation applied to the content of each module
+    pub const fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    ///
+    /// Changes the rotation applied to the content of each module.
+    /// Existing content is not rotated.
+    ///
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    /// Width of the frame buffer in pixels
+    pub const fn width(&self) -> usize {
+        D * 8
+    }
+
+    /// Height of the frame buffer in pixels
+    pub const fn height(&self) -> usize {
+        MAX_DIGITS
+    }
+
+    ///
+    /// Sets a pixel on or off, out of range pixels are ignored
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - column across the whole chain
+    /// * `y` - row `0` to `7`
+    /// * `on` - whether the pixel is lit
+    ///
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        if x < D * 8 && y < MAX_DIGITS {
+            let (column, row) = self.rotation.apply(x % 8, y);
+            let bit = 0b1000_0000 >> column;
+            let byte = self.frame[x / 8][row];
+            self.set_byte(x / 8, row, if on { byte | bit } else { byte & !bit });
+        }
+    }
+
+    ///
+    /// Returns whether a pixel is lit, out of range pixels are dark
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - column across the whole chain
+    /// * `y` - row `0` to `7`
+    ///
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.rotation.pixel(&self.frame, x, y)
+    }
+
+    ///
+    /// Turns all pixels off
+    ///
+    pub fn clear(&mut self) {
+        self.set_frame(&[[0x00; MAX_DIGITS]; D]);
+    }
+
+    ///
+    /// Returns the raw display bytes as used by `write_frame`
+    ///
+    pub fn frame(&self) -> &[[u8; MAX_DIGITS]; D] {
+        &self.frame
+    }
+
+    ///
+    /// Replaces the content with raw display bytes as used by `write_frame`
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - raw bytes per display as connected in series (0 -> last)
+    ///
+    pub fn set_frame(&mut self, frame: &[[u8; MAX_DIGITS]; D]) {
+        for (addr, raw) in frame.iter().enumerate() {
+            for (row, byte) in raw.iter().enumerate() {
+                self.set_byte(addr, row, *byte);
+            }
+        }
+    }
+
+    ///
+    /// Draws a sprite with its top left corner at given position.
+    /// The sprite may cross display boundaries and is clipped at the frame edges.
+    ///
+    /// # Arguments
+    ///
+    /// * `sprite` - the sprite to draw
+    /// * `x` - column of the left sprite edge, may be negative
+    /// * `y` - row of the top sprite edge, may be negative
+    ///
+    pub fn draw_sprite(&mut self, sprite: &Sprite, x: i32, y: i32) {
+        for sy in 0..sprite.height() {
+            for sx in 0..sprite.width() {
+                let (Some(fx), Some(fy)) = (x.checked_add(sx as i32), y.checked_add(sy as i32))
+                else {
+                    continue;
+                };
+                if fx >= 0 && fy >= 0 && sprite.is_opaque(sx, sy) {
+                    self.set_pixel(fx as usize, fy as usize, sprite.pixel(sx, sy));
+                }
+            }
+        }
+    }
+
+    ///
+    /// Marks all rows dirty so the next flush rewrites the whole chain
+    ///
+    pub fn invalidate(&mut self) {
+        self.dirty = 0xFF;
+    }
+
+    ///
+    /// Returns whether any row changed since the last flush
+    ///
+    pub fn is_dirty(&self) -> bool {
+        self.dirty != 0
+    }
+
+    ///
+    /// Sends rows changed since the last flush to the displays,
+    /// one row of the whole chain per transfer
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn flush<CONNECTOR>(
+        &mut self,
+        display: &mut MAX7219<D, CONNECTOR>,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        if self.dirty == 0 {
+            return Ok(());
+        }
+
+        display
+            .set_decode_mask_all(DecodeMode::NoDecode as u8)
+            .await?;
+
+        for row in 0..MAX_DIGITS {
+            if self.dirty & (1 << row) == 0 {
+                continue;
+            }
+
+            let mut buffers = [[0; 2]; D];
+            for (buffer, raw) in buffers.iter_mut().zip(&self.frame) {
+                *buffer = [row as u8 + 1, raw[row]];
+            }
+
+            display.write_raw_bytes(buffers.as_flattened()).await?;
+            self.dirty &= !(1 << row);
+        }
+
+        Ok(())
+    }
+
+    // sets a raw byte marking its row dirty when it changes
+    fn set_byte(&mut self, addr: usize, row: usize, byte: u8) {
+        if self.frame[addr][row] != byte {
+            self.frame[addr][row] = byte;
+            self.dirty |= 1 << row;
+        }
+    }
+}
+
+impl<const D: usize> Default for FrameBuffer<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, recorder, take};
+
+    #[test]
+    fn flushes_dirty_rows_only() {
+        let mut framebuffer = FrameBuffer::<2>::new();
+        let mut display = recorder::<2>();
+
+        block_on(framebuffer.flush(&mut display)).unwrap();
+        assert!(!framebuffer.is_dirty());
+        take(&mut display);
+
+        framebuffer.set_pixel(0, 2, true);
+        framebuffer.set_pixel(15, 5, true);
+        framebuffer.set_pixel(15, 5, true);
+        block_on(framebuffer.flush(&mut display)).unwrap();
+        assert_eq!(
+            take(&mut display),
+            [[0x03, 0x80, 0x03, 0x00], [0x06, 0x00, 0x06, 0x01]]
+        );
+
+        // unchanged pixels keep rows clean
+        framebuffer.set_pixel(0, 2, true);
+        framebuffer.set_pixel(16, 0, true);
+        assert!(!framebuffer.is_dirty());
+
+        framebuffer.invalidate();
+        block_on(framebuffer.flush(&mut display)).unwrap();
+        assert_eq!(take(&mut display).len(), MAX_DIGITS);
+    }
+
+    #[test]
+    fn rotates_modules() {
+        let cases = [
+            (Rotation::None, 1, 0b0100_0000),
+            (Rotation::Deg90, 1, 0b0000_0010),
+            (Rotation::Deg180, 6, 0b0000_0010),
+            (Rotation::Deg270, 6, 0b0100_0000),
+        ];
+        for (rotation, row, byte) in cases {
+            let mut framebuffer = FrameBuffer::<2>::with_rotation(rotation);
+            framebuffer.set_pixel(9, 1, true);
+
+            let mut frame = [[0x00; MAX_DIGITS]; 2];
+            frame[1][row] = byte;
+            assert_eq!(framebuffer.frame(), &frame, "{rotation:?}");
+            assert!(framebuffer.pixel(9, 1));
+            assert!(!framebuffer.pixel(1, 9));
+        }
+    }
+
+    #[test]
+    fn clips_sprites() {
+        let sprite = Sprite::new(2, 2, &[0xC0, 0xC0]);
+        let mut framebuffer = FrameBuffer::<1>::new();
+
+        framebuffer.draw_sprite(&sprite, -1, -1);
+        framebuffer.draw_sprite(&sprite, 7, 7);
+        framebuffer.draw_sprite(&sprite, i32::MAX, i32::MAX);
+
+        let mut frame = [0x00; MAX_DIGITS];
+        frame[0] = 0x80;
+        frame[7] = 0x01;
+        assert_eq!(framebuffer.frame(), &[frame]);
+    }
+
+    #[test]
+    fn keeps_dark_masked_pixels() {
+        let sprite = Sprite::new(2, 1, &[0x80]).with_mask(&[0xC0]);
+        let mut framebuffer = FrameBuffer::<1>::new();
+        framebuffer.set_frame(&[[0xFF; MAX_DIGITS]]);
+
+        framebuffer.draw_sprite(&sprite, 3, 0);
+
+        assert_eq!(framebuffer.frame()[0][0], 0b1111_0111);
+        assert_eq!(framebuffer.frame()[0][1], 0xFF);
+    }
+}
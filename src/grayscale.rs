@@ -0,0 +1,269 @@
+//! Software grayscale on 8x8 matrix displays
+//!
+//! The chip only supports a single intensity per display. Grayscale is
+//! emulated by showing the bit-planes of every pixel one after another,
+//! each for a time proportional to its weight (binary coded modulation).
+//! Pixels are addressed across the whole chain, column `x` running from bit 7
+//! of display 0 to bit 0 of display `D - 1` and row `y` being digit `y`.
+
+use core::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::connectors::Connector;
+use crate::fade::delay_us;
+use crate::{DataError, MAX_DIGITS, MAX7219};
+
+/// Bits sent per display and row, register address and data
+const BITS_PER_ROW: u32 = 16;
+
+///
+/// Grayscale frame over all chained displays with `BITS` bits per pixel (2 to 4)
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GrayscaleFrame<const D: usize, const BITS: usize> {
+    pixels: [[[u8; 8]; MAX_DIGITS]; D],
+    phase: u8,
+}
+
+impl<const D: usize, const BITS: usize> GrayscaleFrame<D, BITS> {
+    /// Number of gray levels, level `LEVELS - 1` being fully lit
+    pub const LEVELS: u8 = {
+        assert!(
+            BITS >= 2 && BITS <= 4,
+            "grayscale supports 2 to 4 bits per pixel"
+        );
+        1 << BITS
+    };
+
+    /// Time units one full cycle over all bit-planes takes
+    pub const CYCLE_UNITS: u32 = (1 << BITS) - 1;
+
+    ///
+    /// Construct a new blank frame
+    ///
+    pub const fn new() -> Self {
+        let _ = Self::LEVELS;
+
+        GrayscaleFrame {
+            pixels: [[[0; 8]; MAX_DIGITS]; D],
+            phase: 0,
+        }
+    }
+
+    /// Width of the frame in pixels
+    pub const fn width(&self) -> usize {
+        D * 8
+    }
+
+    ///
+    /// Sets gray level of a pixel, out of range pixels are ignored
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - column across the whole chain
+    /// * `y` - row `0` to `7`
+    /// * `level` - gray level, clamped to `LEVELS - 1`
+    ///
+    pub fn set_pixel(&mut self, x: usize, y: usize, level: u8) {
+        if x < D * 8 && y < MAX_DIGITS {
+            self.pixels[x / 8][y][x % 8] = level.min(Self::LEVELS - 1);
+        }
+    }
+
+    ///
+    /// Returns gray level of a pixel, out of range pixels are dark
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - column across the whole chain
+    /// * `y` - row `0` to `7`
+    ///
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        if x < D * 8 && y < MAX_DIGITS {
+            self.pixels[x / 8][y][x % 8]
+        } else {
+            0
+        }
+    }
+
+    ///
+    /// Sets all pixels to level 0
+    ///
+    pub fn clear(&mut self) {
+        self.pixels = [[[0; 8]; MAX_DIGITS]; D];
+    }
+
+    ///
+    /// Returns a bit-plane as raw display bytes as used by `write_frame`
+    ///
+    /// # Arguments
+    ///
+    /// * `bit` - the bit of the gray level to extract, 0 being the least significant
+    ///
+    pub fn plane(&self, bit: usize) -> [[u8; MAX_DIGITS]; D] {
+        let mut frame = [[0; MAX_DIGITS]; D];
+        for (raw, pixels) in frame.iter_mut().zip(&self.pixels) {
+            for (byte, row) in raw.iter_mut().zip(pixels) {
+                for (column, level) in row.iter().enumerate() {
+                    if level & (1 << bit) != 0 {
+                        *byte |= 0b1000_0000 >> column;
+                    }
+                }
+            }
+        }
+
+        frame
+    }
+
+    ///
+    /// Returns the highest achievable refresh rate of the whole grayscale
+    /// cycle in Hz for given SPI clock, `0` without displays. Per transfer
+    /// overhead such as the CS toggling is not accounted for.
+    ///
+    /// # Arguments
+    ///
+    /// * `spi_hz` - SPI clock frequency in Hz
+    ///
+    pub const fn refresh_rate(spi_hz: u32) -> u32 {
+        // each time unit has to be at least as long as writing one plane
+        let plane_bits = BITS_PER_ROW as u64 * MAX_DIGITS as u64 * D as u64;
+        if plane_bits == 0 {
+            return 0;
+        }
+
+        (spi_hz as u64 / (plane_bits * Self::CYCLE_UNITS as u64)) as u32
+    }
+
+    ///
+    /// Shows one full grayscale cycle by writing each bit-plane and
+    /// holding it for its weight in time units
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `delay` - delay provider used to hold the planes
+    /// * `unit` - time the least significant plane is shown
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn render<CONNECTOR, DELAY>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        delay: &mut DELAY,
+        unit: Duration,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+        DELAY: DelayNs,
+    {
+        let unit_us = unit.as_micros().min(u64::MAX as u128) as u64;
+        for bit in 0..BITS {
+            display.write_frame(&self.plane(bit)).await?;
+            delay_us(delay, unit_us.saturating_mul(1 << bit)).await;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Advances the grayscale cycle by one time unit, writing the next
+    /// bit-plane when its time slot begins. Call at a fixed rate from
+    /// a timer tick instead of using `render`.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn tick<CONNECTOR>(
+        &mut self,
+        display: &mut MAX7219<D, CONNECTOR>,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        // plane `n` starts at unit `2^n - 1` and lasts `2^n` units
+        let slot = self.phase + 1;
+        if slot.is_power_of_two() {
+            let bit = slot.trailing_zeros() as usize;
+            display.write_frame(&self.plane(bit)).await?;
+        }
+
+        self.phase = (self.phase + 1) % Self::CYCLE_UNITS as u8;
+
+        Ok(())
+    }
+}
+
+impl<const D: usize, const BITS: usize> Default for GrayscaleFrame<D, BITS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Waits, block_on, recorder, take};
+
+    fn gradient() -> GrayscaleFrame<2, 2> {
+        let mut frame = GrayscaleFrame::new();
+        for x in 0..frame.width() {
+            frame.set_pixel(x, 0, (x % 4) as u8);
+        }
+        frame.set_pixel(16, 0, 3);
+        frame
+    }
+
+    #[test]
+    fn extracts_bit_planes() {
+        let frame = gradient();
+
+        assert_eq!(frame.plane(0)[0][0], 0b0101_0101);
+        assert_eq!(frame.plane(1)[1][0], 0b0011_0011);
+        assert_eq!(frame.plane(1)[0][1..], [0x00; 7]);
+        assert_eq!(frame.pixel(16, 0), 0);
+    }
+
+    #[test]
+    fn ticks_planes_by_weight() {
+        let mut frame = gradient();
+        let mut display = recorder::<2>();
+
+        // plane 0 for one unit, plane 1 for two units
+        let mut rows = [0; 6];
+        for row in rows.iter_mut() {
+            block_on(frame.tick(&mut display)).unwrap();
+            *row = display.registers[0].digits[0];
+        }
+        take(&mut display);
+
+        assert_eq!(rows, [0x55, 0x33, 0x33, 0x55, 0x33, 0x33]);
+    }
+
+    #[test]
+    fn renders_long_units() {
+        let frame = gradient();
+        let mut display = recorder::<2>();
+        let mut delay = Waits::default();
+
+        let unit = Duration::from_secs(5_000);
+        block_on(frame.render(&mut display, &mut delay, unit)).unwrap();
+
+        assert_eq!(delay.total(), unit * 3);
+        assert_eq!(display.registers[1].digits[0], 0x33);
+    }
+
+    #[test]
+    fn limits_refresh_rate() {
+        assert_eq!(GrayscaleFrame::<1, 4>::refresh_rate(10_000_000), 5_208);
+        assert_eq!(GrayscaleFrame::<4, 2>::refresh_rate(10_000_000), 6_510);
+        assert_eq!(GrayscaleFrame::<0, 2>::refresh_rate(10_000_000), 0);
+    }
+}
@@ -0,0 +1,357 @@
+//! Monochrome bitmap decoding for matrix displays
+//!
+//! Supports plain (`P1`) and raw (`P4`) PBM as well as XBM images. Images
+//! are validated once when parsed and decoded straight from the borrowed
+//! bytes when drawn, so they work with `include_bytes!` without copying.
+//! Set (black) pixels are lit.
+
+use crate::MAX_DIGITS;
+use crate::framebuffer::{FrameBuffer, Rotation};
+
+///
+/// Error raised in case an image could not be parsed
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageError {
+    /// The data is neither PBM nor XBM
+    UnsupportedFormat,
+    /// The header or pixel data is malformed
+    Malformed,
+    /// The pixel data ends before all pixels were read
+    Truncated,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Format {
+    PlainPbm,
+    RawPbm,
+    Xbm,
+}
+
+///
+/// A parsed monochrome image
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Image<'a> {
+    format: Format,
+    width: usize,
+    height: usize,
+    // pixel data following the header
+    data: &'a [u8],
+}
+
+impl<'a> Image<'a> {
+    ///
+    /// Parses and validates a PBM (`P1`, `P4`) or XBM image
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - the image file content
+    ///
+    /// # Errors
+    ///
+    /// * `ImageError` - returned in case the image could not be parsed
+    ///
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ImageError> {
+        let image = match bytes {
+            [b'P', b'1', ..] => Self::parse_pbm(Format::PlainPbm, &bytes[2..])?,
+            [b'P', b'4', ..] => Self::parse_pbm(Format::RawPbm, &bytes[2..])?,
+            _ if bytes.starts_with(b"#define") => Self::parse_xbm(bytes)?,
+            _ => return Err(ImageError::UnsupportedFormat),
+        };
+
+        // pixel positions have to fit when drawn at an offset
+        let pixels = image.width.checked_mul(image.height);
+        if image.width > i32::MAX as usize || image.height > i32::MAX as usize || pixels.is_none() {
+            return Err(ImageError::Malformed);
+        }
+        image.for_each_pixel(|_, _, _| {})?;
+
+        Ok(image)
+    }
+
+    /// Width in pixels
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    ///
+    /// Draws the image with its top left corner at given position,
+    /// both lit and dark pixels are drawn
+    ///
+    /// # Arguments
+    ///
+    /// * `framebuffer` - the frame buffer to draw into, its rotation is honored
+    /// * `x` - column of the left image edge, may be negative
+    /// * `y` - row of the top image edge, may be negative
+    ///
+    pub fn draw<const D: usize>(&self, framebuffer: &mut FrameBuffer<D>, x: i32, y: i32) {
+        // validated by parse
+        let _ = self.for_each_pixel(|ix, iy, on| {
+            // pixels whose position does not fit an i32 are off the frame anyway
+            let fx = i32::try_from(ix).ok().and_then(|ix| x.checked_add(ix));
+            let fy = i32::try_from(iy).ok().and_then(|iy| y.checked_add(iy));
+            if let (Some(fx @ 0..), Some(fy @ 0..)) = (fx, fy) {
+                framebuffer.set_pixel(fx as usize, fy as usize, on);
+            }
+        });
+    }
+
+    ///
+    /// Converts the top left part of the image into raw display bytes as used by `write_frame`
+    ///
+    /// # Arguments
+    ///
+    /// * `rotation` - rotation applied to the content of each module
+    ///
+    pub fn to_frame<const D: usize>(&self, rotation: Rotation) -> [[u8; MAX_DIGITS]; D] {
+        let mut framebuffer = FrameBuffer::<D>::with_rotation(rotation);
+        self.draw(&mut framebuffer, 0, 0);
+
+        *framebuffer.frame()
+    }
+
+    ///
+    /// Calls `pixel` with the column, row and state of every pixel, row by row
+    ///
+    /// # Errors
+    ///
+    /// * `ImageError` - returned in case the pixel data is malformed
+    ///
+    pub fn for_each_pixel(
+        &self,
+        mut pixel: impl FnMut(usize, usize, bool),
+    ) -> Result<(), ImageError> {
+        let stride = self.width.div_ceil(8);
+
+        match self.format {
+            Format::PlainPbm => {
+                let mut bits = self.data.iter().filter(|b| !b.is_ascii_whitespace());
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        match bits.next() {
+                            Some(b'0') => pixel(x, y, false),
+                            Some(b'1') => pixel(x, y, true),
+                            Some(_) => return Err(ImageError::Malformed),
+                            None => return Err(ImageError::Truncated),
+                        }
+                    }
+                }
+            }
+            Format::RawPbm => {
+                let len = stride
+                    .checked_mul(self.height)
+                    .ok_or(ImageError::Malformed)?;
+                if self.data.len() < len {
+                    return Err(ImageError::Truncated);
+                }
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let byte = self.data[y * stride + x / 8];
+                        pixel(x, y, byte & (0b1000_0000 >> (x % 8)) != 0);
+                    }
+                }
+            }
+            Format::Xbm => {
+                let mut tokens = Tokens::new(self.data);
+                for y in 0..self.height {
+                    for column in 0..stride {
+                        let byte = match tokens.next_number() {
+                            Some(n) if n <= 0xFF => n as u8,
+                            Some(_) => return Err(ImageError::Malformed),
+                            None => return Err(ImageError::Truncated),
+                        };
+                        // XBM stores the leftmost pixel in the lowest bit
+                        for bit in 0..8 {
+                            let x = column * 8 + bit;
+                            if x < self.width {
+                                pixel(x, y, byte & (1 << bit) != 0);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_pbm(format: Format, bytes: &'a [u8]) -> Result<Self, ImageError> {
+        let mut tokens = Tokens::new(bytes);
+        let width = tokens.next_number().ok_or(ImageError::Malformed)? as usize;
+        let height = tokens.next_number().ok_or(ImageError::Malformed)? as usize;
+
+        // a single whitespace separates the header from the pixel data
+        let data = tokens.rest().get(1..).unwrap_or_default();
+
+        Ok(Image {
+            format,
+            width,
+            height,
+            data,
+        })
+    }
+
+    fn parse_xbm(bytes: &'a [u8]) -> Result<Self, ImageError> {
+        let mut width = None;
+        let mut height = None;
+
+        for line in bytes.split(|b| *b == b'\n') {
+            let mut words = line
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|w| !w.is_empty());
+            if words.next() != Some(b"#define") {
+                continue;
+            }
+            let name = words.next().unwrap_or_default();
+            let value = words.next().and_then(|v| Tokens::new(v).next_number());
+            if name.ends_with(b"_width") {
+                width = value;
+            } else if name.ends_with(b"_height") {
+                height = value;
+            }
+        }
+
+        let start = bytes
+            .iter()
+            .position(|b| *b == b'{')
+            .ok_or(ImageError::Malformed)?;
+
+        Ok(Image {
+            format: Format::Xbm,
+            width: width.ok_or(ImageError::Malformed)? as usize,
+            height: height.ok_or(ImageError::Malformed)? as usize,
+            data: &bytes[start + 1..],
+        })
+    }
+}
+
+// splits header and XBM data into numbers, skipping whitespace, separators and comments
+struct Tokens<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Tokens<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Tokens { bytes }
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    // parses the next decimal or `0x` prefixed hex number
+    fn next_number(&mut self) -> Option<u32> {
+        loop {
+            match self.bytes.first()? {
+                b'#' => {
+                    let end = self.bytes.iter().position(|b| *b == b'\n')?;
+                    self.bytes = &self.bytes[end..];
+                }
+                b'}' => return None,
+                b',' => self.bytes = &self.bytes[1..],
+                b if b.is_ascii_whitespace() => self.bytes = &self.bytes[1..],
+                _ => break,
+            }
+        }
+
+        let (radix, digits) = match self.bytes {
+            [b'0', b'x' | b'X', rest @ ..] => (16, rest),
+            rest => (10, rest),
+        };
+        let len = digits
+            .iter()
+            .take_while(|b| (**b as char).is_digit(radix))
+            .count();
+        if len == 0 {
+            return None;
+        }
+
+        let number = core::str::from_utf8(&digits[..len]).ok()?;
+        self.bytes = &digits[len..];
+
+        u32::from_str_radix(number, radix).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+    use std::vec::Vec;
+
+    fn pixels(image: &Image) -> Vec<(usize, usize)> {
+        let mut lit = Vec::new();
+        image
+            .for_each_pixel(|x, y, on| {
+                if on {
+                    lit.push((x, y));
+                }
+            })
+            .unwrap();
+        lit
+    }
+
+    #[test]
+    fn parses_plain_pbm() {
+        let image = Image::parse(b"P1\n# comment\n3 2\n1 0 0\n0 0 1\n").unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(pixels(&image), [(0, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn parses_raw_pbm() {
+        let image = Image::parse(b"P4 10 2\n\x80\x40\x00\x40").unwrap();
+        assert_eq!(pixels(&image), [(0, 0), (9, 0), (9, 1)]);
+    }
+
+    #[test]
+    fn parses_xbm() {
+        let xbm = b"#define dot_width 9\n#define dot_height 1\n\
+                    static char dot_bits[] = { 0x01, 0x01 };";
+        let image = Image::parse(xbm).unwrap();
+        // XBM stores the leftmost pixel in the lowest bit
+        assert_eq!(pixels(&image), [(0, 0), (8, 0)]);
+    }
+
+    #[test]
+    fn clips_drawn_images() {
+        let image = Image::parse(b"P1 2 2\n1 1\n1 1\n").unwrap();
+        let mut framebuffer = FrameBuffer::<1>::new();
+
+        image.draw(&mut framebuffer, i32::MAX, 0);
+        image.draw(&mut framebuffer, 0, i32::MAX);
+        assert_eq!(framebuffer.frame(), &[[0x00; MAX_DIGITS]]);
+
+        image.draw(&mut framebuffer, -1, 7);
+        let mut frame = [0x00; MAX_DIGITS];
+        frame[7] = 0x80;
+        assert_eq!(framebuffer.frame(), &[frame]);
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        assert_eq!(Image::parse(b"GIF89a"), Err(ImageError::UnsupportedFormat));
+        assert_eq!(Image::parse(b"P4 16 2\n\x00"), Err(ImageError::Truncated));
+        assert_eq!(Image::parse(b"P1 2 1\n1 2"), Err(ImageError::Malformed));
+        assert_eq!(Image::parse(b"P4 x 1\n"), Err(ImageError::Malformed));
+    }
+
+    #[test]
+    fn rejects_oversized_headers() {
+        assert_eq!(
+            Image::parse(b"P4 4294967295 4294967295\n\x00"),
+            Err(ImageError::Malformed)
+        );
+        assert_eq!(
+            Image::parse(b"P1 4294967295 0\n"),
+            Err(ImageError::Malformed)
+        );
+    }
+}
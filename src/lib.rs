@@ -7,12 +7,72 @@
 #![deny(unsafe_code)]
 #![no_std]
 
-use embedded_hal::digital::OutputPin;
-use embedded_hal_async::spi::SpiDevice;
+use embedded_hal::digital::{OutputPin, PinState};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::{SpiBus, SpiDevice};
 
 pub mod connectors;
 use connectors::*;
 
+pub mod format;
+pub use format::{Align, NumberFormat, Overflow, Radix, Sign};
+
+pub mod window;
+pub use window::DigitWindow;
+
+pub mod code_b;
+
+pub mod widgets;
+
+pub mod fade;
+
+pub mod grayscale;
+
+pub mod framebuffer;
+pub use framebuffer::{FrameBuffer, Rotation};
+
+pub mod animation;
+
+pub mod transition;
+pub use transition::Transition;
+
+pub mod image;
+
+pub mod bitmap;
+
+pub mod font;
+
+pub mod loopback;
+pub use loopback::ChainDiagnostics;
+
+pub mod refresh;
+pub use refresh::RefreshPolicy;
+
+pub mod sleep;
+pub use sleep::AutoSleep;
+
+pub mod power;
+pub use power::{PowerLimiter, PowerModel};
+
+pub mod brightness;
+pub use brightness::{AutoBrightness, BrightnessSchedule};
+
+pub mod calibration;
+
+pub mod diagnostics;
+
+pub mod service;
+pub use service::{DisplayCommand, DisplayService};
+
+#[cfg(feature = "linux")]
+pub mod linux;
+
+mod registers;
+use registers::{RESYNC_ORDER, Registers};
+
+#[cfg(test)]
+mod testing;
+
 /// Digits per display
 const MAX_DIGITS: usize = 8;
 
@@ -37,6 +97,7 @@ pub enum Command {
 }
 
 /// Decode modes for BCD encoded input.
+/// For arbitrary per digit decoding see `MAX7219::set_decode_mask`.
 #[derive(Clone, Copy, PartialEq, Debug)]
 #[repr(u8)]
 pub enum DecodeMode {
@@ -46,16 +107,28 @@ pub enum DecodeMode {
     CodeBDigits7_0 = 0xFF,
 }
 
+/// Selects the display(s) an operation applies to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    /// Single display as connected in series (0 -> last)
+    Display(usize),
+    /// All connected displays
+    All,
+}
+
 ///
 /// Error raised in case there was an error
 /// during communication with the MAX7219 chip.
 ///
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum DataError {
     /// An error occurred when working with SPI
     Spi,
     /// An error occurred when working with a PIN
     Pin,
+    /// The given character can not be encoded
+    UnsupportedChar(u8),
 }
 
 ///
@@ -67,7 +140,11 @@ pub enum DataError {
 ///
 pub struct MAX7219<const D: usize, CONNECTOR> {
     connector: CONNECTOR,
-    decode_mode: DecodeMode,
+    registers: [Registers; D],
+    synced: bool,
+    code_b_text: bool,
+    intensity_offsets: [i8; D],
+    sleeping: Option<[bool; D]>,
 }
 
 impl<const D: usize, CONNECTOR> MAX7219<D, CONNECTOR>
@@ -82,11 +159,7 @@ where
     /// * `DataError` - returned in case there was an error during data transfer
     ///
     pub async fn power_on(&mut self) -> Result<(), DataError> {
-        for i in 0..D {
-            self.write_command(i, Command::Power, 0x01).await?;
-        }
-
-        Ok(())
+        self.set_power_all(true).await
     }
 
     ///
@@ -97,11 +170,76 @@ where
     /// * `DataError` - returned in case there was an error during data transfer
     ///
     pub async fn power_off(&mut self) -> Result<(), DataError> {
-        for i in 0..D {
-            self.write_command(i, Command::Power, 0x00).await?;
+        self.set_power_all(false).await
+    }
+
+    ///
+    /// Powers a single display on or off. Digit registers keep their
+    /// content in shutdown, so powering on shows it again right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - display to address as connected in series (0 -> last)
+    /// * `is_on` - whether to power on or shut down
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn set_power(&mut self, addr: usize, is_on: bool) -> Result<(), DataError> {
+        self.write_command(addr, Command::Power, is_on as u8).await
+    }
+
+    ///
+    /// Powers all displays on or off in a single transfer
+    ///
+    /// # Arguments
+    ///
+    /// * `is_on` - whether to power on or shut down
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn set_power_all(&mut self, is_on: bool) -> Result<(), DataError> {
+        self.write_command_all(Command::Power, is_on as u8).await
+    }
+
+    ///
+    /// Powers each display on or off in a single transfer
+    ///
+    /// # Arguments
+    ///
+    /// * `states` - whether to power on each display as connected in series (0 -> last)
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn set_power_each(&mut self, states: &[bool; D]) -> Result<(), DataError> {
+        self.write_raw_bytes(Self::power_buffers(states).as_flattened())
+            .await
+    }
+
+    // power register writes for all displays in a single transfer
+    fn power_buffers(states: &[bool; D]) -> [[u8; 2]; D] {
+        let mut buffers = [[0; 2]; D];
+        for (buffer, is_on) in buffers.iter_mut().zip(states) {
+            *buffer = [Command::Power as u8, *is_on as u8];
         }
 
-        Ok(())
+        buffers
+    }
+
+    ///
+    /// Whether a display was last powered on by the driver
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - display to address as connected in series (0 -> last)
+    ///
+    pub fn is_powered(&self, addr: usize) -> bool {
+        self.registers[addr].power & 0x01 != 0
     }
 
     ///
@@ -131,17 +269,11 @@ where
     /// * `DataError` - returned in case there was an error during data transfer
     ///
     pub async fn clear_all_displays(&mut self) -> Result<(), DataError> {
-        let mut buffers = [[0; 2]; D];
-        let buffer = buffers.as_flattened_mut();
-
         for digit in 1..9 {
-            for display in 0..D {
-                buffer[display * 2] = digit;
-                buffer[display * 2 + 1] = 0x00;
-            }
+            self.write_raw_byte_all(digit, 0x00).await?;
         }
 
-        self.write_raw_bytes(buffer).await
+        Ok(())
     }
 
     ///
@@ -178,15 +310,63 @@ where
         addr: usize,
         mode: DecodeMode,
     ) -> Result<(), DataError> {
-        if self.decode_mode != mode {
-            self.decode_mode = mode;
-            self.write_command(addr, Command::DecodeMode, mode as u8)
-                .await?;
+        self.set_decode_mask(addr, mode as u8).await
+    }
+
+    ///
+    /// Sets Code-B decoding per digit. Digits with their bit set are
+    /// decoded from Code-B, the others take raw segment bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - display to address as connected in series (0 -> last)
+    /// * `mask` - u8 bit array of digits to decode, bit 0 being the rightmost digit (`Digit0`)
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn set_decode_mask(&mut self, addr: usize, mask: u8) -> Result<(), DataError> {
+        if self.registers[addr].decode_mask != mask {
+            self.write_command(addr, Command::DecodeMode, mask).await?;
+        }
+
+        Ok(())
+    }
+
+    // sets the same decode mask on all displays in a single transfer
+    pub(crate) async fn set_decode_mask_all(&mut self, mask: u8) -> Result<(), DataError> {
+        if self.registers.iter().any(|r| r.decode_mask != mask) {
+            self.write_command_all(Command::DecodeMode, mask).await?;
         }
 
         Ok(())
     }
 
+    ///
+    /// Returns the Code-B decode mask last set on given display
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - display to address as connected in series (0 -> last)
+    ///
+    pub fn decode_mask(&self, addr: usize) -> u8 {
+        self.registers[addr].decode_mask
+    }
+
+    ///
+    /// Selects how text and numbers are sent to the display. When enabled
+    /// characters with a Code-B glyph are sent as Code-B decoded digits,
+    /// all others as raw segments. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - whether to use Code-B decoding where possible
+    ///
+    pub fn set_code_b_text(&mut self, enabled: bool) {
+        self.code_b_text = enabled;
+    }
+
     ///
     /// Writes byte string to the display
     ///
@@ -206,36 +386,23 @@ where
         string: &[u8; MAX_DIGITS],
         dots: u8,
     ) -> Result<(), DataError> {
-        let prev_dm = self.decode_mode;
-        self.set_decode_mode(0, DecodeMode::NoDecode).await?;
-
-        let mut digit: u8 = MAX_DIGITS as u8;
-        let mut dot_product: u8 = 0b1000_0000;
-        for b in string {
-            let dot = (dots & dot_product) > 0;
-            dot_product >>= 1;
-            self.write_raw_byte(addr, digit, ssb_byte(*b, dot)).await?;
-
-            digit -= 1;
-        }
-
-        self.set_decode_mode(0, prev_dm).await?;
-
-        Ok(())
+        self.write_ascii(addr, 0, string, dots).await
     }
 
     ///
-    /// Writes BCD encoded string to the display
+    /// Writes BCD encoded string to the display. All digits are left in Code-B decode mode.
     ///
     /// # Arguments
     ///
     /// * `addr` - display to address as connected in series (0 -> last)
     /// * `bcd`  - the bcd encoded string slice consisting of [0-9,-,E,L,H,P]
-    ///            where upper case input for alphabetic characters results in dot being set.
-    ///            Length of string is always 8 bytes, use spaces for blanking.
+    ///   where upper case input for alphabetic characters results in dot being set.
+    ///   Length of string is always 8 bytes, use spaces for blanking.
     ///
     /// # Errors
     ///
+    /// * `DataError::UnsupportedChar` - returned in case the string contains a character
+    ///   without Code-B glyph, nothing is written in that case
     /// * `DataError` - returned in case there was an error during data transfer
     ///
     pub async fn write_bcd(
@@ -243,18 +410,21 @@ where
         addr: usize,
         bcd: &[u8; MAX_DIGITS],
     ) -> Result<(), DataError> {
-        let prev_dm = self.decode_mode;
-        self.set_decode_mode(0, DecodeMode::CodeBDigits7_0).await?;
+        let mut codes = [0u8; MAX_DIGITS];
+        for (code, b) in codes.iter_mut().zip(bcd) {
+            *code = bcd_byte(*b)?;
+        }
+
+        self.set_decode_mode(addr, DecodeMode::CodeBDigits7_0)
+            .await?;
 
         let mut digit: u8 = MAX_DIGITS as u8;
-        for b in bcd {
-            self.write_raw_byte(addr, digit, bcd_byte(*b)).await?;
+        for code in codes {
+            self.write_raw_byte(addr, digit, code).await?;
 
             digit -= 1;
         }
 
-        self.set_decode_mode(0, prev_dm).await?;
-
         Ok(())
     }
 
@@ -268,34 +438,59 @@ where
     ///
     /// # Errors
     ///
-    /// * `DataError` - returned in case there was an integer over flow
+    /// * `DataError` - returned in case there was an error during data transfer
     ///
     pub async fn write_integer(&mut self, addr: usize, value: i32) -> Result<(), DataError> {
-        let mut buf = [0u8; 8];
-        let j = base_10_bytes(value, &mut buf);
-        buf = pad_left(j);
-        self.write_str(addr, &buf, 0b00000000).await?;
-        Ok(())
+        self.write_number(addr, 0, value.into(), &NumberFormat::decimal())
+            .await
     }
 
     ///
-    /// Writes a right justified hex formatted integer with sign
+    /// Writes a right justified hex formatted integer
     ///
     /// # Arguments
     ///
     /// * `addr` - display to address as connected in series (0 -> last)
-    /// * `val` - an integer i32
+    /// * `val` - an integer u32
     ///
     /// # Errors
     ///
-    /// * `DataError` - returned in case there was an integer over flow
+    /// * `DataError` - returned in case there was an error during data transfer
     ///
     pub async fn write_hex(&mut self, addr: usize, value: u32) -> Result<(), DataError> {
-        let mut buf = [0u8; 8];
-        let j = hex_bytes(value, &mut buf);
-        buf = pad_left(j);
-        self.write_str(addr, &buf, 0b00000000).await?;
-        Ok(())
+        self.write_number(addr, 0, value.into(), &NumberFormat::hex())
+            .await
+    }
+
+    ///
+    /// Writes a formatted number into a field of digits. Only the digits
+    /// covered by the field are rewritten so multiple numbers can share a display.
+    /// Digits of the field beyond the display are cut off.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - display to address as connected in series (0 -> last)
+    /// * `position` - first digit of the field counted from the left (0 -> leftmost)
+    /// * `value` - the number to write
+    /// * `format` - describes field width, radix, alignment, sign and overflow handling
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_number(
+        &mut self,
+        addr: usize,
+        position: usize,
+        value: i64,
+        format: &NumberFormat,
+    ) -> Result<(), DataError> {
+        let mut buf = [b' '; MAX_DIGITS];
+        let available = MAX_DIGITS.saturating_sub(position);
+        let len = format.format(value, &mut buf[..available]);
+
+        self.write_ascii(addr, position, &buf[..len], 0b0000_0000)
+            .await
     }
 
     ///
@@ -315,16 +510,37 @@ where
         addr: usize,
         raw: &[u8; MAX_DIGITS],
     ) -> Result<(), DataError> {
-        let prev_dm = self.decode_mode;
         self.set_decode_mode(addr, DecodeMode::NoDecode).await?;
 
-        let mut digit: u8 = 1;
-        for b in raw {
+        for (digit, b) in (1..).zip(raw) {
             self.write_raw_byte(addr, digit, *b).await?;
-            digit += 1;
         }
 
-        self.set_decode_mode(addr, prev_dm).await?;
+        Ok(())
+    }
+
+    ///
+    /// Writes raw values to all displays, one row (digit) of the whole chain per transfer
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - raw bytes per display as connected in series (0 -> last), same layout as `write_digits`
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_frame(&mut self, frame: &[[u8; MAX_DIGITS]; D]) -> Result<(), DataError> {
+        self.set_decode_mask_all(DecodeMode::NoDecode as u8).await?;
+
+        for (digit, row) in (1..).zip(0..MAX_DIGITS) {
+            let mut buffers = [[0; 2]; D];
+            for (buffer, raw) in buffers.iter_mut().zip(frame) {
+                *buffer = [digit, raw[row]];
+            }
+
+            self.write_raw_bytes(buffers.as_flattened()).await?;
+        }
 
         Ok(())
     }
@@ -358,27 +574,151 @@ where
             .await
     }
 
+    // writes ASCII bytes starting at given digit position from the left,
+    // dots bits are aligned to the leftmost byte. Depending on `code_b_text`
+    // digits are sent Code-B decoded where possible, decode bits of digits
+    // outside the written range are left untouched.
+    pub(crate) async fn write_ascii(
+        &mut self,
+        addr: usize,
+        position: usize,
+        string: &[u8],
+        dots: u8,
+    ) -> Result<(), DataError> {
+        // digits beyond the display are cut off
+        let string = &string[..string.len().min(MAX_DIGITS.saturating_sub(position))];
+        if string.is_empty() {
+            return Ok(());
+        }
+
+        let mut codes = [0u8; MAX_DIGITS];
+        let mut mask = self.registers[addr].decode_mask;
+
+        let mut dot_product: u8 = 0b1000_0000;
+        for (i, b) in string.iter().enumerate() {
+            let dot = (dots & dot_product) > 0;
+            dot_product >>= 1;
+            let bit = 1 << (MAX_DIGITS - 1 - position - i);
+
+            match code_b::encode(*b, dot) {
+                Ok(code) if self.code_b_text => {
+                    codes[i] = code;
+                    mask |= bit;
+                }
+                _ => {
+                    codes[i] = ssb_byte(*b, dot);
+                    mask &= !bit;
+                }
+            }
+        }
+
+        self.set_decode_mask(addr, mask).await?;
+
+        let mut digit = (MAX_DIGITS - position) as u8;
+        for code in &codes[..string.len()] {
+            self.write_raw_byte(addr, digit, *code).await?;
+
+            digit -= 1;
+        }
+
+        Ok(())
+    }
+
     // internal constructor, users should call ::from_pins or ::from_spi
     fn new(connector: CONNECTOR) -> Self {
         MAX7219 {
             connector,
-            decode_mode: DecodeMode::NoDecode,
+            registers: [Registers::new(); D],
+            synced: true,
+            code_b_text: false,
+            intensity_offsets: [0; D],
+            sleeping: None,
         }
     }
 
     pub async fn init(&mut self) -> Result<(), DataError> {
-        for i in 0..D {
-            self.test(i, false).await?;
-            self.write_command(i, Command::ScanLimit, 0x07).await?;
-            self.set_decode_mode(i, DecodeMode::NoDecode).await?;
-            self.clear_display(i).await?;
+        self.write_command_all(Command::DisplayTest, 0x00).await?;
+        self.write_command_all(Command::ScanLimit, 0x07).await?;
+        self.write_command_all(Command::DecodeMode, DecodeMode::NoDecode as u8)
+            .await?;
+        self.clear_all_displays().await?;
+        self.power_off().await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Whether the displays are known to match the driver state. This is not
+    /// the case after a transfer failed or its future was dropped before completion.
+    ///
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    ///
+    /// Rewrites all registers of all displays from the driver state, one
+    /// transfer per register. Called automatically before the next transfer
+    /// once the displays are out of sync.
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn resync(&mut self) -> Result<(), DataError> {
+        self.synced = false;
+
+        for command in RESYNC_ORDER {
+            let mut buffers = [[0; 2]; D];
+            for (buffer, registers) in buffers.iter_mut().zip(&self.registers) {
+                *buffer = [command as u8, registers.value(command)];
+            }
+
+            self.connector
+                .write_raw_bytes(buffers.as_flattened())
+                .await?;
         }
 
-        self.power_off().await?;
+        self.synced = !self.connector.take_dirty();
 
         Ok(())
     }
 
+    ///
+    /// Marks the displays out of sync, so the next write rewrites all registers
+    /// from the driver state first. Useful after a failure the driver can not
+    /// see, such as a brown-out of the displays.
+    ///
+    pub fn mark_dirty(&mut self) {
+        self.synced = false;
+    }
+
+    ///
+    /// Retries failed transfers according to given policy, waiting with the
+    /// delay provider between attempts
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - delay provider used for the backoff
+    /// * `policy` - number of attempts and backoff to apply
+    ///
+    pub fn with_retry<T>(
+        self,
+        delay: T,
+        policy: RetryPolicy,
+    ) -> MAX7219<D, RetryConnector<CONNECTOR, T>>
+    where
+        T: DelayNs,
+    {
+        MAX7219 {
+            connector: RetryConnector::new(self.connector, delay, policy),
+            registers: self.registers,
+            synced: self.synced,
+            code_b_text: self.code_b_text,
+            intensity_offsets: self.intensity_offsets,
+            sleeping: self.sleeping,
+        }
+    }
+
     ///
     /// Writes data to given register as described by command
     ///
@@ -443,21 +783,52 @@ where
     }
 
     ///
-    /// Writes data to given register as described by command
+    /// Writes raw bytes to the chain in a single transfer. A complete transfer of
+    /// one `[header, data]` pair per display is recorded in the driver state.
+    /// The displays are resynced first if they are out of sync and woken up
+    /// first if an [`AutoSleep`] shut them down.
     ///
     /// # Arguments
     ///
-    /// * `addr` - display to address as connected in series (0 -> last)
-    /// * `header` - the command/register on the display to write to as u8
-    /// * `data` - the data byte value to write
+    /// * `buffer` - the bytes to send, one `[header, data]` pair per display by address
     ///
     /// # Errors
     ///
     /// * `DataError` - returned in case there was an error during data transfer
     ///
-    #[inline]
     pub async fn write_raw_bytes(&mut self, buffer: &[u8]) -> Result<(), DataError> {
-        self.connector.write_raw_bytes(buffer).await
+        self.prepare_write().await?;
+        self.send(buffer).await
+    }
+
+    // brings the displays up to date before a write
+    async fn prepare_write(&mut self) -> Result<(), DataError> {
+        if !self.synced {
+            self.resync().await?;
+        }
+        self.wake().await?;
+
+        Ok(())
+    }
+
+    // writes and records a buffer
+    async fn send(&mut self, buffer: &[u8]) -> Result<(), DataError> {
+        // stays cleared if the transfer fails or is dropped before completion
+        self.synced = false;
+        self.connector.write_raw_bytes(buffer).await?;
+        self.synced = !self.connector.take_dirty();
+        self.record(buffer);
+
+        Ok(())
+    }
+
+    // mirrors a completely sent buffer in the register state
+    fn record(&mut self, buffer: &[u8]) {
+        if buffer.len() == D * 2 {
+            for (registers, pair) in self.registers.iter_mut().zip(buffer.chunks_exact(2)) {
+                registers.apply(pair[0], pair[1]);
+            }
+        }
     }
 }
 
@@ -486,6 +857,55 @@ where
     }
 }
 
+impl<const D: usize, DATA, CS, SCK, DELAY> MAX7219<D, PinConnector<DATA, CS, SCK, DELAY>>
+where
+    DATA: OutputPin,
+    CS: OutputPin,
+    SCK: OutputPin,
+    DELAY: DelayNs,
+{
+    ///
+    /// Times the bit-banged transfers with given delay provider and timing profile.
+    /// Use `PinTiming::DATASHEET` on fast MCUs and longer delays for long cables.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - delay provider used between pin changes
+    /// * `timing` - the pulse, setup and hold times to apply
+    ///
+    pub fn with_timing<T>(
+        self,
+        delay: T,
+        timing: PinTiming,
+    ) -> MAX7219<D, PinConnector<DATA, CS, SCK, T>>
+    where
+        T: DelayNs,
+    {
+        MAX7219 {
+            connector: self.connector.with_timing(delay, timing),
+            registers: self.registers,
+            synced: self.synced,
+            code_b_text: self.code_b_text,
+            intensity_offsets: self.intensity_offsets,
+            sleeping: self.sleeping,
+        }
+    }
+
+    ///
+    /// Sets the levels SCK and DATA are left at between transfers, both low by default.
+    /// The levels are applied with the next transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - idle level of SCK
+    /// * `data` - idle level of DATA
+    ///
+    pub fn with_idle_levels(mut self, clock: PinState, data: PinState) -> Self {
+        self.connector.set_idle_levels(clock, data);
+        self
+    }
+}
+
 impl<const D: usize, SPI> MAX7219<D, SpiConnector<SPI>>
 where
     SPI: SpiDevice<u8>,
@@ -538,24 +958,111 @@ where
     }
 }
 
+impl<const D: usize, BUS, CS> MAX7219<D, SpiBusConnector<BUS, CS>>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    ///
+    /// Construct a new MAX7219 driver instance from an exclusively owned SPI bus
+    /// and CS pin set to output. The CS pin is driven by the driver around each transfer.
+    ///
+    /// * `NOTE` - make sure the bus is initialized in `SPI_MODE` (MODE_0) with max 10 Mhz frequency.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - the SPI bus initialized with MOSI, MISO(unused) and CLK
+    /// * `cs` - the CS PIN used to LOAD register on the display set to output mode
+    ///
+    pub fn from_spi_bus(bus: BUS, cs: CS) -> Self {
+        MAX7219::new(SpiBusConnector::new(bus, cs))
+    }
+}
+
+impl<const D: usize, SPI> MAX7219<D, BlockingSpiConnector<SPI>>
+where
+    SPI: embedded_hal::spi::SpiDevice<u8>,
+{
+    ///
+    /// Construct a new MAX7219 driver instance from a blocking SPI device. Use this with
+    /// the `embedded-hal-bus` device wrappers to share a bus with other devices,
+    /// the wrapper drives the CS (LOAD) line.
+    ///
+    /// * `NOTE` - make sure the bus is initialized in `SPI_MODE` (MODE_0) with max 10 Mhz frequency.
+    ///
+    /// # Arguments
+    ///
+    /// * `spi` - the SPI device sharing a bus initialized with MOSI, MISO(unused) and CLK
+    ///
+    pub fn from_blocking_spi(spi: SPI) -> Self {
+        MAX7219::new(BlockingSpiConnector::new(spi))
+    }
+}
+
+impl<const D: usize, BUS, CS, const CHAINS: usize> MAX7219<D, MultiChainConnector<BUS, CS, CHAINS>>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    /// Displays per chain
+    pub const CHAIN_LEN: usize = D / CHAINS;
+
+    ///
+    /// Construct a new MAX7219 driver instance driving `CHAINS` chains of `D / CHAINS`
+    /// displays each from an exclusively owned SPI bus and one CS pin per chain.
+    /// Frames are written row by row across all chains to keep them in sync.
+    ///
+    /// * `NOTE` - make sure the bus is initialized in `SPI_MODE` (MODE_0) with max 10 Mhz frequency.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - the SPI bus initialized with MOSI, MISO(unused) and CLK
+    /// * `cs` - the CS PINs used to LOAD registers on each chain set to output mode
+    ///
+    /// # Panics
+    ///
+    /// * if `D` is not a multiple of `CHAINS`
+    ///
+    pub fn from_spi_bus_chains(bus: BUS, cs: [CS; CHAINS]) -> Self {
+        assert!(
+            CHAINS > 0 && D.is_multiple_of(CHAINS),
+            "displays must split evenly into chains"
+        );
+
+        MAX7219::new(MultiChainConnector::new(bus, cs))
+    }
+
+    ///
+    /// Returns the flat address of a display as used by all other methods
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - the chain by order of CS pins
+    /// * `index` - display within the chain as connected in series (0 -> last)
+    ///
+    pub const fn chain_addr(chain: usize, index: usize) -> usize {
+        chain * Self::CHAIN_LEN + index
+    }
+
+    ///
+    /// Splits a flat address into chain and display within the chain
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - flat address of the display
+    ///
+    pub const fn split_addr(addr: usize) -> (usize, usize) {
+        (addr / Self::CHAIN_LEN, addr % Self::CHAIN_LEN)
+    }
+}
+
 ///
 /// Translate alphanumeric ASCII bytes into BCD
 /// encoded bytes expected by the display chip.
+/// Upper case letters turn the dot on.
 ///
-fn bcd_byte(b: u8) -> u8 {
-    match b as char {
-        ' ' => 0b0000_1111, // "blank"
-        '-' => 0b0000_1010, // - without .
-        'e' => 0b0000_1011, // E without .
-        'E' => 0b1000_1011, // E with .
-        'h' => 0b0000_1100, // H without .
-        'H' => 0b1000_1100, // H with .
-        'l' => 0b0000_1101, // L without .
-        'L' => 0b1000_1101, // L with .
-        'p' => 0b0000_1110, // L without .
-        'P' => 0b1000_1110, // L with .
-        _ => b,
-    }
+fn bcd_byte(b: u8) -> Result<u8, DataError> {
+    code_b::encode(b, matches!(b, b'E' | b'H' | b'L' | b'P'))
 }
 
 ///
@@ -566,6 +1073,7 @@ fn ssb_byte(b: u8, dot: bool) -> u8 {
         ' ' => 0b0000_0000, // "blank"
         '.' => 0b1000_0000,
         '-' => 0b0000_0001, // -
+        '+' => 0b0011_0001, // -| approximation of +
         '_' => 0b0000_1000, // _
         '0' => 0b0111_1110,
         '1' => 0b0011_0000,
@@ -613,87 +1121,48 @@ fn ssb_byte(b: u8, dot: bool) -> u8 {
     result
 }
 
-///
-/// Convert the integer into an integer byte Sequence
-///
-fn base_10_bytes(mut n: i32, buf: &mut [u8]) -> &[u8] {
-    let mut sign: bool = false;
-    //don't overflow the display
-    if !(-9999999..99999999).contains(&n) {
-        return b"Err";
-    }
-    if n == 0 {
-        return b"0";
-    }
-    if n < 0 {
-        n = -n;
-        sign = true;
-    }
-    let mut i = 0;
-    while n > 0 {
-        buf[i] = (n % 10) as u8 + b'0';
-        n /= 10;
-        i += 1;
-    }
-    if sign {
-        buf[i] = b'-';
-        i += 1;
-    }
-    let slice = &mut buf[..i];
-    slice.reverse();
-    &*slice
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, recorder, take};
 
-//
-/// Convert the integer into a hexidecimal byte Sequence
-///
-fn hex_bytes(mut n: u32, buf: &mut [u8]) -> &[u8] {
-    // don't overflow the display ( 0xFFFFFFF)
-    if n == 0 {
-        return b"0";
-    }
-    let mut i = 0;
-    while n > 0 {
-        let digit = (n % 16) as u8;
-        buf[i] = match digit {
-            0 => b'0',
-            1 => b'1',
-            2 => b'2',
-            3 => b'3',
-            4 => b'4',
-            5 => b'5',
-            6 => b'6',
-            7 => b'7',
-            8 => b'8',
-            9 => b'9',
-            10 => b'a',
-            11 => b'b',
-            12 => b'c',
-            13 => b'd',
-            14 => b'e',
-            15 => b'f',
-            _ => b'?',
+    #[test]
+    fn write_number_cuts_off_beyond_display() {
+        let mut display = recorder::<1>();
+        let format = NumberFormat {
+            width: 4,
+            ..NumberFormat::decimal()
         };
-        n /= 16;
-        i += 1;
+
+        // the field is cut to the two rightmost display digits
+        block_on(display.write_number(0, 6, 12, &format)).unwrap();
+        let transfers = take(&mut display);
+        assert_eq!(
+            transfers[transfers.len() - 2..],
+            [[0x02, ssb_byte(b'1', false)], [0x01, ssb_byte(b'2', false)]]
+        );
+
+        block_on(display.write_number(0, 9, 1234, &format)).unwrap();
+        assert!(take(&mut display).is_empty());
     }
-    let slice = &mut buf[..i];
-    slice.reverse();
-    &*slice
-}
 
-///
-/// Take a byte slice and pad the left hand side
-///
-fn pad_left(val: &[u8]) -> [u8; 8] {
-    assert!(val.len() <= 8);
-    let size: usize = 8;
-    let pos: usize = val.len();
-    let mut cur: usize = 1;
-    let mut out: [u8; 8] = *b"        ";
-    while cur <= pos {
-        out[size - cur] = val[pos - cur];
-        cur += 1;
-    }
-    out
+    #[test]
+    fn records_complete_transfers_only() {
+        let mut display = recorder::<2>();
+
+        block_on(display.write_raw_bytes(&[0x0A, 0x05, 0x0A, 0x06])).unwrap();
+        block_on(display.write_raw_bytes(&[0x0A, 0x0F])).unwrap();
+        assert_eq!((display.intensity(0), display.intensity(1)), (5, 6));
+
+        display.connector.fail = 1;
+        assert!(block_on(display.write_raw_bytes(&[0x0A, 0x01, 0x0A, 0x01])).is_err());
+        assert_eq!((display.intensity(0), display.intensity(1)), (5, 6));
+
+        // the next write rewrites all registers from the recorded state first
+        take(&mut display);
+        block_on(display.set_power_all(true)).unwrap();
+        let transfers = take(&mut display);
+        assert_eq!(transfers.len(), RESYNC_ORDER.len() + 1);
+        assert!(transfers.contains(&[0x0A, 0x05, 0x0A, 0x06].to_vec()));
+    }
 }
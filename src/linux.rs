@@ -0,0 +1,269 @@
+//! Linux host support over spidev and GPIO character devices
+//!
+//! Enabled with the `linux` feature. Devices come from `linux-embedded-hal`:
+//! a [`SpidevDevice`] with the kernel chip select drives the displays through
+//! the blocking SPI connector, see `from_spidev`, and a [`CdevPin`] can serve
+//! as a separate CS line. [`IoConnector`] sends each transfer to any
+//! `std::io::Write` transport in a single write, so besides the spidev device
+//! of a [`SpidevBus`] it works with pipes, files or in-memory buffers when no
+//! hardware is around.
+
+extern crate std;
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use embedded_hal::digital::{ErrorKind, ErrorType, OutputPin};
+use linux_embedded_hal::spidev::{SpiModeFlags, Spidev, SpidevOptions};
+pub use linux_embedded_hal::{CdevPin, SpidevBus, SpidevDevice};
+
+use crate::connectors::{BlockingSpiConnector, Connector, MAX_SPI_FREQUENCY_HZ};
+use crate::{DataError, MAX7219};
+
+/// Connector writing to a file-like transport with an optional CS pin
+pub struct IoConnector<W, CS>
+where
+    W: Write,
+    CS: OutputPin,
+{
+    transport: W,
+    cs: CS,
+}
+
+impl<W, CS> IoConnector<W, CS>
+where
+    W: Write,
+    CS: OutputPin,
+{
+    pub(crate) fn new(transport: W, cs: CS) -> Self {
+        IoConnector { transport, cs }
+    }
+
+    /// Releases the transport and CS pin
+    pub fn release(self) -> (W, CS) {
+        (self.transport, self.cs)
+    }
+}
+
+impl<W, CS> Connector for IoConnector<W, CS>
+where
+    W: Write,
+    CS: OutputPin,
+{
+    async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+        self.cs.set_low().map_err(|_| DataError::Pin)?;
+        // CS stays low after a failed write, the next transfer shifts the partial data out
+        self.transfer(bytes)?;
+        self.cs.set_high().map_err(|_| DataError::Pin)?;
+
+        Ok(())
+    }
+}
+
+impl<W, CS> IoConnector<W, CS>
+where
+    W: Write,
+    CS: OutputPin,
+{
+    fn transfer(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+        // spidev turns each write into one transfer, so it must not be split
+        let written = self.transport.write(bytes).map_err(|_| DataError::Spi)?;
+        if written != bytes.len() {
+            return Err(DataError::Spi);
+        }
+
+        self.transport.flush().map_err(|_| DataError::Spi)
+    }
+}
+
+///
+/// CS handled by the transport itself, such as the chip select of the spidev kernel driver
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TransportCs;
+
+impl ErrorType for TransportCs {
+    type Error = ErrorKind;
+}
+
+impl OutputPin for TransportCs {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+///
+/// Returns spidev options for the MAX7219: mode 0, 8 bits per word,
+/// MSB first at most 10 MHz
+///
+/// # Arguments
+///
+/// * `kernel_cs` - whether the kernel drives the chip select, disable when using a `CdevPin`
+///
+pub fn spi_options(kernel_cs: bool) -> SpidevOptions {
+    let mut mode = SpiModeFlags::SPI_MODE_0;
+    if !kernel_cs {
+        mode |= SpiModeFlags::SPI_NO_CS;
+    }
+
+    SpidevOptions::new()
+        .bits_per_word(8)
+        .max_speed_hz(MAX_SPI_FREQUENCY_HZ)
+        .lsb_first(false)
+        .mode(mode)
+        .build()
+}
+
+impl<const D: usize, W, CS> MAX7219<D, IoConnector<W, CS>>
+where
+    W: Write,
+    CS: OutputPin,
+{
+    ///
+    /// Construct a new MAX7219 driver instance writing to a file-like transport.
+    /// Each transfer is sent in a single write between CS going low and high.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - the transport to write to, for example the `Spidev` of a
+    ///   [`SpidevBus`] configured with [`spi_options`]
+    /// * `cs` - the CS PIN used to LOAD register on the display, `TransportCs` if
+    ///   the transport drives it
+    ///
+    pub fn from_io(transport: W, cs: CS) -> Self {
+        MAX7219::new(IoConnector::new(transport, cs))
+    }
+}
+
+impl<const D: usize> MAX7219<D, BlockingSpiConnector<SpidevDevice>> {
+    ///
+    /// Construct a new MAX7219 driver instance on a spidev device using the kernel chip select
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the device, for example `/dev/spidev0.0`
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - returned in case the device could not be opened or configured
+    ///
+    pub fn from_spidev(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut spi = Spidev::open(path)?;
+        spi.configure(&spi_options(true))?;
+
+        Ok(MAX7219::from_blocking_spi(SpidevDevice(spi)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::testing::block_on;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    enum Event {
+        Low,
+        High,
+        Write(Vec<u8>),
+        Flush,
+    }
+
+    // transport and CS pin logging into one shared record, writing at most `limit` bytes
+    #[derive(Clone, Default)]
+    struct Log {
+        events: Rc<RefCell<Vec<Event>>>,
+        limit: Option<usize>,
+    }
+
+    impl Write for Log {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            let len = self.limit.unwrap_or(bytes.len()).min(bytes.len());
+            self.events
+                .borrow_mut()
+                .push(Event::Write(bytes[..len].to_vec()));
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.events.borrow_mut().push(Event::Flush);
+            Ok(())
+        }
+    }
+
+    impl ErrorType for Log {
+        type Error = ErrorKind;
+    }
+
+    impl OutputPin for Log {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.events.borrow_mut().push(Event::Low);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.events.borrow_mut().push(Event::High);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_each_transfer_between_cs() {
+        let log = Log::default();
+        let mut display = MAX7219::<2, _>::from_io(log.clone(), log.clone());
+
+        block_on(display.write_command_all(Command::Intensity, 0x03)).unwrap();
+
+        let intensity = Command::Intensity as u8;
+        assert_eq!(
+            *log.events.borrow(),
+            [
+                Event::Low,
+                Event::Write(std::vec![intensity, 0x03, intensity, 0x03]),
+                Event::Flush,
+                Event::High,
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_cs_low_after_short_write() {
+        let log = Log {
+            limit: Some(1),
+            ..Log::default()
+        };
+        let mut connector = IoConnector::new(log.clone(), log.clone());
+
+        assert!(block_on(connector.write_raw_bytes(&[0x0C, 0x01])).is_err());
+        assert_eq!(
+            *log.events.borrow(),
+            [Event::Low, Event::Write(std::vec![0x0C])]
+        );
+    }
+
+    #[test]
+    fn writes_to_buffer_in_chain_order() {
+        let mut display = MAX7219::<2, _>::from_io(Vec::new(), TransportCs);
+        let frame = [
+            core::array::from_fn(|row| 0xA0 + row as u8),
+            core::array::from_fn(|row| 0xB0 + row as u8),
+        ];
+
+        block_on(display.write_frame(&frame)).unwrap();
+
+        let (bytes, _) = display.connector.release();
+        // decoding is off already, so only the rows are written
+        let mut expected = Vec::new();
+        for row in 0..8 {
+            expected.extend([row + 1, 0xA0 + row, row + 1, 0xB0 + row]);
+        }
+        assert_eq!(bytes, expected);
+    }
+}
@@ -0,0 +1,363 @@
+//! Chain verification by reading DOUT of the last display back
+//!
+//! DOUT of each MAX7219 echoes the data shifted in 16 clocks earlier, so with
+//! DOUT of the last display wired to MISO a chain of `n` displays returns each
+//! byte `2 * n` bytes after it was sent. Shifting a known pattern through the
+//! chain reveals its length and broken links, and reading back what is shifted
+//! out by the next transfer confirms frames arrived intact.
+//!
+//! All patterns are sent with No-Op register headers, so probing never changes
+//! what the displays show.
+
+use crate::connectors::LoopbackConnector;
+use crate::{DataError, DecodeMode, MAX_DIGITS, MAX7219};
+
+///
+/// State of the DOUT line seen at the end of the chain
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dout {
+    /// The probe pattern came back intact
+    Echoing,
+    /// DOUT stays low, a link is broken or a display is missing power
+    StuckLow,
+    /// DOUT stays high, a link is broken or floating
+    StuckHigh,
+    /// Data comes back but does not match, the chain is longer than
+    /// twice the expected length or the signal is disturbed
+    Corrupted,
+}
+
+///
+/// Result of a chain verification
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChainDiagnostics {
+    /// Number of displays the driver was set up for
+    pub expected_len: usize,
+    /// Number of displays detected from the echo delay, up to twice the expected length
+    pub detected_len: Option<usize>,
+    /// State of the DOUT line
+    pub dout: Dout,
+    /// Bit array of frame rows which did not come back intact (bit 0 = row 0)
+    pub corrupted_rows: u8,
+}
+
+impl ChainDiagnostics {
+    /// Whether the chain has the expected length and all data came back intact
+    pub fn is_ok(&self) -> bool {
+        self.dout == Dout::Echoing
+            && self.detected_len == Some(self.expected_len)
+            && self.corrupted_rows == 0
+    }
+}
+
+impl<const D: usize, CONNECTOR> MAX7219<D, CONNECTOR>
+where
+    CONNECTOR: LoopbackConnector,
+{
+    ///
+    /// Shifts a No-Op pattern through the chain to detect its length and a stuck DOUT line.
+    /// Chains of up to twice the expected length are detected.
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn probe_chain(&mut self) -> Result<ChainDiagnostics, DataError> {
+        // pattern pairs followed by twice as many No-Op pairs to shift it out
+        let mut write = [[[0x00; 2]; D]; 3];
+        for (i, pair) in write[0].iter_mut().enumerate() {
+            *pair = [probe_header(i), 0xA5 ^ (i as u8).wrapping_mul(0x3B)];
+        }
+        let write = write.as_flattened().as_flattened();
+        let mut read = [[[0x00; 2]; D]; 3];
+        let read = read.as_flattened_mut().as_flattened_mut();
+
+        self.transfer_raw_bytes(read, write).await?;
+
+        let pattern = &write[..D * 2];
+        let detected_len = (0..=D * 2)
+            .find(|len| read[len * 2..].starts_with(pattern))
+            .filter(|_| D > 0);
+
+        // once the stale content is shifted out only echoes remain
+        let tail = &read[D * 4..];
+        let dout = match detected_len {
+            Some(_) => Dout::Echoing,
+            None if tail.iter().all(|b| *b == 0x00) => Dout::StuckLow,
+            None if tail.iter().all(|b| *b == 0xFF) => Dout::StuckHigh,
+            None => Dout::Corrupted,
+        };
+
+        Ok(ChainDiagnostics {
+            expected_len: D,
+            detected_len,
+            dout,
+            corrupted_rows: 0,
+        })
+    }
+
+    ///
+    /// Writes raw values to all displays like `write_frame`, reading each row back
+    /// while the next one is sent. The last row is read back with a No-Op transfer.
+    /// Returns a bit array of rows which did not come back intact (bit 0 = row 0).
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - raw bytes per display as connected in series (0 -> last)
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_frame_verified(
+        &mut self,
+        frame: &[[u8; MAX_DIGITS]; D],
+    ) -> Result<u8, DataError> {
+        self.set_decode_mask_all(DecodeMode::NoDecode as u8).await?;
+
+        let mut corrupted = 0;
+        let mut previous = [[0x00; 2]; D];
+        // the extra transfer sends No-Ops to shift the last row out
+        for row in 0..=MAX_DIGITS {
+            let mut write = [[0x00; 2]; D];
+            if row < MAX_DIGITS {
+                for (pair, raw) in write.iter_mut().zip(frame) {
+                    *pair = [row as u8 + 1, raw[row]];
+                }
+            }
+            let mut read = [[0x00; 2]; D];
+
+            self.transfer_raw_bytes(read.as_flattened_mut(), write.as_flattened())
+                .await?;
+
+            if row > 0 && read != previous {
+                corrupted |= 1 << (row - 1);
+            }
+            previous = write;
+        }
+
+        Ok(corrupted)
+    }
+
+    ///
+    /// Runs a complete chain verification for factory test: probes the chain
+    /// and, if it echoes, writes given frame verifying every row
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - raw bytes per display written as test image
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn diagnose_chain(
+        &mut self,
+        frame: &[[u8; MAX_DIGITS]; D],
+    ) -> Result<ChainDiagnostics, DataError> {
+        let mut diagnostics = self.probe_chain().await?;
+
+        if diagnostics.dout == Dout::Echoing {
+            diagnostics.corrupted_rows = self.write_frame_verified(frame).await?;
+        }
+
+        Ok(diagnostics)
+    }
+
+    // like write_raw_bytes while reading DOUT back
+    async fn transfer_raw_bytes(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), DataError> {
+        self.prepare_write().await?;
+
+        self.synced = false;
+        self.connector.transfer_raw_bytes(read, write).await?;
+        self.synced = !self.connector.take_dirty();
+        self.record(write);
+
+        Ok(())
+    }
+}
+
+// distinct No-Op headers, the register address in the low nibble stays 0
+const fn probe_header(i: usize) -> u8 {
+    ((i % 15) as u8 + 1) << 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::connectors::Connector;
+    use crate::testing::block_on;
+
+    extern crate std;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    // what DOUT of the last display returns
+    enum Line {
+        // shifts bytes through `n` displays, flipping a bit of the first byte
+        // read back by the loopback transfer with given index
+        Chain { n: usize, corrupt: Option<usize> },
+        Stuck(u8),
+    }
+
+    struct Loopback {
+        line: Line,
+        shift: VecDeque<u8>,
+        loopbacks: usize,
+        transfers: Vec<Vec<u8>>,
+    }
+
+    impl Loopback {
+        fn new(line: Line) -> Self {
+            let n = match line {
+                Line::Chain { n, .. } => n,
+                Line::Stuck(_) => 0,
+            };
+            Loopback {
+                line,
+                shift: core::iter::repeat_n(0x00, n * 2).collect(),
+                loopbacks: 0,
+                transfers: Vec::new(),
+            }
+        }
+
+        fn shift(&mut self, read: &mut [u8], write: &[u8]) {
+            for (out, byte) in read.iter_mut().zip(write) {
+                self.shift.push_back(*byte);
+                let shifted = self.shift.pop_front().unwrap_or(*byte);
+                *out = match self.line {
+                    Line::Chain { .. } => shifted,
+                    Line::Stuck(level) => level,
+                };
+            }
+            self.transfers.push(write.to_vec());
+        }
+    }
+
+    impl Connector for Loopback {
+        async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+            let mut read = std::vec![0x00; bytes.len()];
+            self.shift(&mut read, bytes);
+            Ok(())
+        }
+    }
+
+    impl LoopbackConnector for Loopback {
+        async fn transfer_raw_bytes(
+            &mut self,
+            read: &mut [u8],
+            write: &[u8],
+        ) -> Result<(), DataError> {
+            self.shift(read, write);
+            if let Line::Chain {
+                corrupt: Some(index),
+                ..
+            } = self.line
+                && index == self.loopbacks
+            {
+                read[0] ^= 0x01;
+            }
+            self.loopbacks += 1;
+            Ok(())
+        }
+    }
+
+    fn chain<const D: usize>(line: Line) -> MAX7219<D, Loopback> {
+        MAX7219::new(Loopback::new(line))
+    }
+
+    fn echoing(n: usize) -> Line {
+        Line::Chain { n, corrupt: None }
+    }
+
+    const FRAME: [[u8; MAX_DIGITS]; 2] = [[0x81; MAX_DIGITS], [0x3C; MAX_DIGITS]];
+
+    #[test]
+    fn detects_chain_length() {
+        for n in 1..=4 {
+            let diagnostics = block_on(chain::<2>(echoing(n)).probe_chain()).unwrap();
+
+            assert_eq!(diagnostics.detected_len, Some(n));
+            assert_eq!(diagnostics.dout, Dout::Echoing);
+            assert_eq!(diagnostics.is_ok(), n == 2);
+        }
+
+        // longer than twice the expected length the pattern does not come back
+        let diagnostics = block_on(chain::<2>(echoing(5)).probe_chain()).unwrap();
+        assert_eq!(diagnostics.detected_len, None);
+        assert_eq!(diagnostics.dout, Dout::Corrupted);
+    }
+
+    #[test]
+    fn detects_stuck_dout() {
+        let low = block_on(chain::<2>(Line::Stuck(0x00)).probe_chain()).unwrap();
+        let high = block_on(chain::<2>(Line::Stuck(0xFF)).probe_chain()).unwrap();
+
+        assert_eq!((low.detected_len, low.dout), (None, Dout::StuckLow));
+        assert_eq!((high.detected_len, high.dout), (None, Dout::StuckHigh));
+        assert!(!low.is_ok() && !high.is_ok());
+    }
+
+    #[test]
+    fn probes_without_changing_registers() {
+        let mut display = chain::<2>(echoing(2));
+        block_on(display.set_intensity_all(0x05)).unwrap();
+        let registers = display.registers;
+
+        block_on(display.probe_chain()).unwrap();
+        assert_eq!(display.registers, registers);
+        assert!(display.is_synced());
+
+        // only transfers of one pair per display are mirrored
+        let intensity = Command::Intensity as u8;
+        let write = [intensity, 0x0F].repeat(6);
+        let mut read = [0x00; 12];
+        block_on(display.transfer_raw_bytes(&mut read, &write)).unwrap();
+        assert_eq!(display.registers, registers);
+    }
+
+    #[test]
+    fn verifies_frame_rows() {
+        let mut display = chain::<2>(echoing(2));
+        assert_eq!(block_on(display.write_frame_verified(&FRAME)).unwrap(), 0);
+        assert_eq!(display.registers[1].digits, [0x3C; MAX_DIGITS]);
+
+        // the transfer sending row 3 reads row 2 back
+        let corrupt = Some(3);
+        let mut display = chain::<2>(Line::Chain { n: 2, corrupt });
+        assert_eq!(
+            block_on(display.write_frame_verified(&FRAME)).unwrap(),
+            0b0000_0100
+        );
+
+        // the last row is read back by the trailing No-Op transfer
+        let corrupt = Some(MAX_DIGITS);
+        let mut display = chain::<2>(Line::Chain { n: 2, corrupt });
+        assert_eq!(
+            block_on(display.write_frame_verified(&FRAME)).unwrap(),
+            0b1000_0000
+        );
+    }
+
+    #[test]
+    fn diagnoses_chain() {
+        let diagnostics = block_on(chain::<2>(echoing(2)).diagnose_chain(&FRAME)).unwrap();
+        assert!(diagnostics.is_ok());
+
+        // the probe is the first loopback transfer
+        let corrupt = Some(1 + 5);
+        let mut display = chain::<2>(Line::Chain { n: 2, corrupt });
+        let diagnostics = block_on(display.diagnose_chain(&FRAME)).unwrap();
+        assert_eq!(diagnostics.corrupted_rows, 0b0001_0000);
+        assert!(!diagnostics.is_ok());
+
+        // a stuck chain is not written to
+        let mut display = chain::<2>(Line::Stuck(0x00));
+        let diagnostics = block_on(display.diagnose_chain(&FRAME)).unwrap();
+        assert_eq!(diagnostics.corrupted_rows, 0);
+        assert_eq!(display.connector.transfers.len(), 1);
+    }
+}
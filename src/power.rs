@@ -0,0 +1,461 @@
+//! Supply current estimation and power budget limiting
+//!
+//! The peak segment current is set by the resistor on ISET and drops
+//! slightly with the LED forward voltage, as tabled in the datasheet. Digits
+//! are multiplexed, so each lit segment draws the peak current for
+//! `1 / (scan limit + 1)` of the time, further reduced by the intensity duty
+//! cycle of `(2n + 1) / 32`. Estimates are in microamps and ignore the current
+//! limit when the supply leaves too little headroom above the forward voltage.
+
+use crate::connectors::Connector;
+use crate::fade::MAX_INTENSITY;
+use crate::framebuffer::FrameBuffer;
+use crate::registers::Registers;
+use crate::{DataError, MAX_DIGITS, MAX7219};
+
+/// LED forward voltages in millivolt of the datasheet RSET table columns
+const LED_FORWARD_MV: [u32; 5] = [1500, 2000, 2500, 3000, 3500];
+
+/// RSET in ohms for a peak segment current in milliamps, per forward voltage
+const RSET_OHMS: [(u32, [u32; 5]); 4] = [
+    (40, [12_200, 11_800, 11_000, 10_600, 9_690]),
+    (30, [17_800, 17_100, 15_800, 15_000, 14_000]),
+    (20, [29_800, 28_000, 25_900, 24_500, 22_600]),
+    (10, [66_700, 63_700, 59_300, 55_400, 51_200]),
+];
+
+/// Supply current in shutdown
+const SHUTDOWN_UA: u32 = 150;
+
+///
+/// Electrical parameters of the displays used to estimate their supply current
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PowerModel {
+    /// Resistor from ISET to V+ in ohms
+    pub rset_ohms: u32,
+    /// Forward voltage of the LEDs in millivolt
+    pub led_forward_mv: u32,
+    /// Supply voltage V+ in millivolt
+    pub supply_mv: u32,
+    /// Supply current of a powered display with all segments off in microamps
+    pub quiescent_ua: u32,
+}
+
+///
+/// Estimated supply current of each display and the whole chain
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PowerEstimate<const D: usize> {
+    /// Current of each display as connected in series (0 -> last) in microamps
+    pub device_ua: [u32; D],
+    /// Current of the whole chain in microamps
+    pub total_ua: u32,
+}
+
+impl PowerModel {
+    ///
+    /// Construct a new model with the typical quiescent current of 8 mA
+    ///
+    /// # Arguments
+    ///
+    /// * `rset_ohms` - resistor from ISET to V+ in ohms
+    /// * `led_forward_mv` - forward voltage of the LEDs in millivolt
+    /// * `supply_mv` - supply voltage in millivolt
+    ///
+    pub const fn new(rset_ohms: u32, led_forward_mv: u32, supply_mv: u32) -> Self {
+        PowerModel {
+            rset_ohms,
+            led_forward_mv,
+            supply_mv,
+            quiescent_ua: 8_000,
+        }
+    }
+
+    ///
+    /// Peak current of a single segment in microamps, interpolated from
+    /// the datasheet RSET table
+    ///
+    pub fn segment_current_ua(&self) -> u32 {
+        let rset = self.rset_ohms.max(1) as u64;
+        let rows = RSET_OHMS.map(|(ma, ohms)| (ma as u64 * 1000, self.table_ohms(&ohms)));
+
+        // currents scale with the conductance of RSET
+        let (low, high) = match rows.iter().position(|(_, ohms)| rset <= *ohms) {
+            // below the table, scale from its highest current
+            Some(0) => return (rows[0].0 * rows[0].1 / rset) as u32,
+            Some(i) => (rows[i], rows[i - 1]),
+            // above the table, scale from its lowest current
+            None => {
+                let (ua, ohms) = rows[rows.len() - 1];
+                return (ua * ohms / rset) as u32;
+            }
+        };
+
+        let conductance = |ohms: u64| 1_000_000_000 / ohms;
+        let span = conductance(high.1) - conductance(low.1);
+        let offset = conductance(rset) - conductance(low.1);
+
+        (low.0 + (high.0 - low.0) * offset / span.max(1)) as u32
+    }
+
+    ///
+    /// Supply current of a single display in microamps
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - raw segments of each digit register
+    /// * `intensity` - intensity register value
+    /// * `scan_limit` - scan limit register value
+    ///
+    pub fn device_current_ua(&self, rows: &[u8; MAX_DIGITS], intensity: u8, scan_limit: u8) -> u32 {
+        let scanned = (scan_limit & 0x07) as usize + 1;
+        let segments: u64 = rows[..scanned]
+            .iter()
+            .map(|row| row.count_ones() as u64)
+            .sum();
+        let duty = 2 * (intensity & MAX_INTENSITY) as u64 + 1;
+
+        let led = self.segment_current_ua() as u64 * segments * duty / (32 * scanned as u64);
+
+        self.quiescent_ua
+            .saturating_add(led.min(u32::MAX as u64) as u32)
+    }
+
+    ///
+    /// Estimates the supply current with the frame buffer content shown
+    /// at the intensity and scan limit last set on each display
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver holding the register state
+    /// * `framebuffer` - the content to estimate for
+    ///
+    pub fn estimate<const D: usize, CONNECTOR>(
+        &self,
+        display: &MAX7219<D, CONNECTOR>,
+        framebuffer: &FrameBuffer<D>,
+    ) -> PowerEstimate<D>
+    where
+        CONNECTOR: Connector,
+    {
+        let device_ua = core::array::from_fn(|addr| {
+            let registers = &display.registers[addr];
+            let rows = &framebuffer.frame()[addr];
+            self.registers_current_ua(registers, rows, registers.intensity)
+        });
+
+        PowerEstimate {
+            device_ua,
+            total_ua: device_ua
+                .iter()
+                .fold(0, |total, ua| total.saturating_add(*ua)),
+        }
+    }
+
+    ///
+    /// Power drawn from the supply in milliwatts
+    ///
+    /// # Arguments
+    ///
+    /// * `current_ua` - supply current in microamps
+    ///
+    pub fn power_mw(&self, current_ua: u32) -> u32 {
+        (current_ua as u64 * self.supply_mv as u64 / 1_000_000) as u32
+    }
+
+    // supply current of a display in given register state showing given rows
+    fn registers_current_ua(
+        &self,
+        registers: &Registers,
+        rows: &[u8; MAX_DIGITS],
+        intensity: u8,
+    ) -> u32 {
+        if registers.test & 0x01 != 0 {
+            // display test lights all segments of all digits at full duty
+            self.device_current_ua(&[0xFF; MAX_DIGITS], MAX_INTENSITY, 0x07)
+        } else if registers.power & 0x01 == 0 {
+            SHUTDOWN_UA
+        } else {
+            self.device_current_ua(rows, intensity, registers.scan_limit)
+        }
+    }
+
+    // RSET of a table row at the forward voltage, interpolated between columns
+    fn table_ohms(&self, ohms: &[u32; 5]) -> u64 {
+        let mv = self
+            .led_forward_mv
+            .clamp(LED_FORWARD_MV[0], LED_FORWARD_MV[4]);
+        let column = LED_FORWARD_MV[..4]
+            .iter()
+            .rposition(|column| mv >= *column)
+            .unwrap_or(0);
+
+        let (mv_low, mv_high) = (LED_FORWARD_MV[column], LED_FORWARD_MV[column + 1]);
+        let (low, high) = (ohms[column] as u64, ohms[column + 1] as u64);
+
+        low - (low - high) * (mv - mv_low) as u64 / (mv_high - mv_low) as u64
+    }
+}
+
+///
+/// Limits the intensity of all displays so the estimated supply current
+/// stays within a budget, applied with every flush
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PowerLimiter {
+    model: PowerModel,
+    budget_ua: u32,
+    intensity: u8,
+}
+
+impl PowerLimiter {
+    ///
+    /// Construct a new limiter allowing up to full intensity
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - electrical parameters of the displays
+    /// * `budget_ua` - highest supply current of the whole chain in microamps
+    ///
+    pub const fn new(model: PowerModel, budget_ua: u32) -> Self {
+        PowerLimiter {
+            model,
+            budget_ua,
+            intensity: MAX_INTENSITY,
+        }
+    }
+
+    /// The model used for estimates
+    pub const fn model(&self) -> &PowerModel {
+        &self.model
+    }
+
+    /// Highest supply current of the whole chain in microamps
+    pub const fn budget_ua(&self) -> u32 {
+        self.budget_ua
+    }
+
+    ///
+    /// Sets the intensity used when the budget allows it
+    ///
+    /// # Arguments
+    ///
+    /// * `intensity` - intensity value from `0x00` to `0x0F`
+    ///
+    pub fn set_intensity(&mut self, intensity: u8) {
+        self.intensity = intensity.min(MAX_INTENSITY);
+    }
+
+    ///
+    /// Highest intensity up to the requested one keeping the frame buffer
+    /// content within the budget, or `0x00` if even that exceeds it.
+    /// Displays in test mode count at full current whatever the intensity.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver holding the register state
+    /// * `framebuffer` - the content to limit for
+    ///
+    pub fn limit<const D: usize, CONNECTOR>(
+        &self,
+        display: &MAX7219<D, CONNECTOR>,
+        framebuffer: &FrameBuffer<D>,
+    ) -> u8
+    where
+        CONNECTOR: Connector,
+    {
+        (0..=self.intensity)
+            .rev()
+            .find(|intensity| {
+                let total = (0..D)
+                    .map(|addr| {
+                        self.model.registers_current_ua(
+                            &display.registers[addr],
+                            &framebuffer.frame()[addr],
+                            display.calibrated_intensity(addr, *intensity),
+                        )
+                    })
+                    .fold(0, u32::saturating_add);
+                total <= self.budget_ua
+            })
+            .unwrap_or(0)
+    }
+
+    ///
+    /// Flushes the frame buffer at the limited intensity, set on all displays in a
+    /// single transfer. A lower intensity is set before and a higher one after
+    /// the content changes, so the budget holds during the flush.
+    /// Returns the intensity set.
+    ///
+    /// # Arguments
+    ///
+    /// * `framebuffer` - the content to flush
+    /// * `display` - the driver to write through
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn flush<const D: usize, CONNECTOR>(
+        &self,
+        framebuffer: &mut FrameBuffer<D>,
+        display: &mut MAX7219<D, CONNECTOR>,
+    ) -> Result<u8, DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let intensity = self.limit(display, framebuffer);
+        let calibrated = |addr| display.calibrated_intensity(addr, intensity);
+        let lower = (0..D).any(|addr| display.intensity(addr) > calibrated(addr));
+        let higher = (0..D).any(|addr| display.intensity(addr) < calibrated(addr));
+
+        if lower {
+            display.set_intensity_all(intensity).await?;
+        }
+        framebuffer.flush(display).await?;
+        if higher && !lower {
+            display.set_intensity_all(intensity).await?;
+        }
+
+        Ok(intensity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::testing::{block_on, recorder, take};
+
+    const MODEL: PowerModel = PowerModel::new(28_000, 2000, 5000);
+
+    #[test]
+    fn reads_segment_current_from_table() {
+        assert_eq!(MODEL.segment_current_ua(), 20_000);
+        // halfway between the 2 V and 2.5 V columns
+        assert_eq!(
+            PowerModel::new(26_950, 2250, 5000).segment_current_ua(),
+            20_000
+        );
+
+        let between = PowerModel::new(22_000, 2000, 5000).segment_current_ua();
+        assert!(20_000 < between && between < 30_000);
+    }
+
+    #[test]
+    fn scales_beyond_table() {
+        assert_eq!(
+            PowerModel::new(5_900, 2000, 5000).segment_current_ua(),
+            80_000
+        );
+        assert_eq!(
+            PowerModel::new(127_400, 2000, 5000).segment_current_ua(),
+            5_000
+        );
+        // forward voltages outside the table use its first column
+        assert_eq!(
+            PowerModel::new(29_800, 500, 5000).segment_current_ua(),
+            PowerModel::new(29_800, 1500, 5000).segment_current_ua()
+        );
+    }
+
+    #[test]
+    fn spreads_current_over_scanned_digits() {
+        let lit = [0xFF; MAX_DIGITS];
+
+        assert_eq!(MODEL.device_current_ua(&lit, 0x0F, 0x07), 8_000 + 155_000);
+        assert_eq!(MODEL.device_current_ua(&lit, 0x0F, 0x00), 8_000 + 155_000);
+        assert_eq!(MODEL.device_current_ua(&lit, 0x00, 0x07), 8_000 + 5_000);
+        assert_eq!(
+            MODEL.device_current_ua(&[0x00; MAX_DIGITS], 0x0F, 0x07),
+            8_000
+        );
+        assert_eq!(MODEL.power_mw(163_000), 815);
+    }
+
+    #[test]
+    fn estimates_by_register_state() {
+        let mut display = recorder::<3>();
+        let mut framebuffer = FrameBuffer::<3>::new();
+        framebuffer.set_frame(&[[0xFF; MAX_DIGITS]; 3]);
+        block_on(async {
+            display.set_power(0, true).await?;
+            display.write_command_all(Command::ScanLimit, 0x07).await?;
+            display.set_intensity_all(0x0F).await?;
+            display.test(2, true).await
+        })
+        .unwrap();
+
+        let estimate = MODEL.estimate(&display, &framebuffer);
+
+        assert_eq!(estimate.device_ua, [163_000, SHUTDOWN_UA, 163_000]);
+        assert_eq!(estimate.total_ua, 326_000 + SHUTDOWN_UA);
+    }
+
+    #[test]
+    fn limits_intensity_to_budget() {
+        let mut display = recorder::<1>();
+        let mut framebuffer = FrameBuffer::<1>::new();
+        framebuffer.set_frame(&[[0xFF; MAX_DIGITS]]);
+        block_on(async {
+            display.power_on().await?;
+            display.write_command_all(Command::ScanLimit, 0x07).await?;
+            display.set_intensity_all(0x0F).await
+        })
+        .unwrap();
+        take(&mut display);
+
+        // 8 mA quiescent and 5 mA per duty step of the 64 lit segments
+        let limiter = PowerLimiter::new(MODEL, 50_000);
+        assert_eq!(limiter.limit(&display, &framebuffer), 3);
+        assert_eq!(
+            PowerLimiter::new(MODEL, 1_000).limit(&display, &framebuffer),
+            0
+        );
+
+        assert_eq!(
+            block_on(limiter.flush(&mut framebuffer, &mut display)).unwrap(),
+            3
+        );
+        let transfers = take(&mut display);
+        assert_eq!(transfers[0], [Command::Intensity as u8, 3]);
+        assert!(transfers.len() > 1);
+    }
+
+    #[test]
+    fn counts_test_mode_at_full_current() {
+        let mut display = recorder::<2>();
+        let mut framebuffer = FrameBuffer::<2>::new();
+        framebuffer.set_frame(&[[0xFF; MAX_DIGITS]; 2]);
+        block_on(async {
+            display.power_on().await?;
+            display.write_command_all(Command::ScanLimit, 0x07).await?;
+            display.test(1, true).await
+        })
+        .unwrap();
+
+        // the display in test mode takes 163 mA of the budget at any intensity
+        let limiter = PowerLimiter::new(MODEL, 163_000 + 50_000);
+        assert_eq!(limiter.limit(&display, &framebuffer), 3);
+    }
+
+    #[test]
+    fn saturates_current_sums() {
+        let model = PowerModel {
+            quiescent_ua: u32::MAX,
+            ..MODEL
+        };
+        let lit = [0xFF; MAX_DIGITS];
+        assert_eq!(model.device_current_ua(&lit, 0x0F, 0x07), u32::MAX);
+
+        let mut display = recorder::<2>();
+        block_on(display.power_on()).unwrap();
+        let framebuffer = FrameBuffer::<2>::new();
+        assert_eq!(model.estimate(&display, &framebuffer).total_ua, u32::MAX);
+        assert_eq!(
+            PowerLimiter::new(model, u32::MAX - 1).limit(&display, &framebuffer),
+            0
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Periodic register refresh from the driver state
+//!
+//! Electrical glitches can flip a MAX7219 into shutdown or test mode or corrupt
+//! its scan limit, where it stays until the registers are written again.
+//! Refreshing rewrites every register of the whole chain from the driver state,
+//! one transfer per register. Unchanged values are latched again without
+//! any visible effect, so a refresh does not flicker.
+
+use core::time::Duration;
+
+use crate::connectors::Connector;
+use crate::{DataError, MAX7219};
+
+///
+/// Rate limited refresh, rewriting all registers once per interval
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RefreshPolicy {
+    interval: Duration,
+    elapsed: Duration,
+}
+
+impl RefreshPolicy {
+    ///
+    /// Construct a new policy, the first refresh is due after one interval
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - time between refreshes, a few seconds are usually enough
+    ///
+    pub const fn new(interval: Duration) -> Self {
+        RefreshPolicy {
+            interval,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Time between refreshes
+    pub const fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Time until the next refresh is due
+    pub fn remaining(&self) -> Duration {
+        self.interval.saturating_sub(self.elapsed)
+    }
+
+    /// Whether a refresh is due
+    pub fn is_due(&self) -> bool {
+        self.elapsed >= self.interval
+    }
+
+    ///
+    /// Advances by elapsed time and refreshes the display once due.
+    /// Returns whether a refresh was done.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to refresh
+    /// * `elapsed` - time since the previous call
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer,
+    ///   the refresh stays due
+    ///
+    pub async fn tick<const D: usize, CONNECTOR>(
+        &mut self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        elapsed: Duration,
+    ) -> Result<bool, DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        self.elapsed = self.elapsed.saturating_add(elapsed);
+        if !self.is_due() {
+            return Ok(false);
+        }
+
+        display.refresh().await?;
+        self.elapsed = Duration::ZERO;
+
+        Ok(true)
+    }
+}
+
+impl<const D: usize, CONNECTOR> MAX7219<D, CONNECTOR>
+where
+    CONNECTOR: Connector,
+{
+    ///
+    /// Rewrites all configuration and digit registers of all displays from the
+    /// driver state, one chained transfer per register
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn refresh(&mut self) -> Result<(), DataError> {
+        self.resync().await
+    }
+}
@@ -0,0 +1,126 @@
+//! Shadow copy of the registers of each display
+//!
+//! Every complete transfer is mirrored here, so the chain can be rewritten
+//! to a known state after a transfer was interrupted or failed.
+
+use crate::{Command, MAX_DIGITS};
+
+/// Registers rewritten by a resync, in order. Digits are written
+/// before the power register so no stale content is shown when powering on.
+pub(crate) const RESYNC_ORDER: [Command; 13] = [
+    Command::DisplayTest,
+    Command::ScanLimit,
+    Command::DecodeMode,
+    Command::Intensity,
+    Command::Digit0,
+    Command::Digit1,
+    Command::Digit2,
+    Command::Digit3,
+    Command::Digit4,
+    Command::Digit5,
+    Command::Digit6,
+    Command::Digit7,
+    Command::Power,
+];
+
+/// Register values of a single display, power-up defaults until written
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Registers {
+    pub digits: [u8; MAX_DIGITS],
+    pub decode_mask: u8,
+    pub intensity: u8,
+    pub scan_limit: u8,
+    pub power: u8,
+    pub test: u8,
+}
+
+impl Registers {
+    pub const fn new() -> Self {
+        Registers {
+            digits: [0x00; MAX_DIGITS],
+            decode_mask: 0x00,
+            intensity: 0x00,
+            scan_limit: 0x00,
+            power: 0x00,
+            test: 0x00,
+        }
+    }
+
+    // records a written register, only the low nibble of the header selects
+    // the register, no-ops and unknown registers are ignored
+    pub fn apply(&mut self, header: u8, data: u8) {
+        let register = header & 0x0F;
+        match register {
+            0x01..=0x08 => self.digits[register as usize - 1] = data,
+            0x09 => self.decode_mask = data,
+            0x0A => self.intensity = data,
+            0x0B => self.scan_limit = data,
+            0x0C => self.power = data,
+            0x0F => self.test = data,
+            _ => {}
+        }
+    }
+
+    pub fn value(&self, command: Command) -> u8 {
+        match command {
+            Command::Noop => 0x00,
+            Command::DecodeMode => self.decode_mask,
+            Command::Intensity => self.intensity,
+            Command::ScanLimit => self.scan_limit,
+            Command::Power => self.power,
+            Command::DisplayTest => self.test,
+            digit => self.digits[digit as usize - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_each_register() {
+        let mut registers = Registers::new();
+        for (header, data) in (0x01..=0x0C).zip(0x11..) {
+            registers.apply(header, data);
+        }
+        registers.apply(0x0F, 0x01);
+
+        assert_eq!(
+            registers.digits,
+            [0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18]
+        );
+        assert_eq!(registers.decode_mask, 0x19);
+        assert_eq!(registers.intensity, 0x1A);
+        assert_eq!(registers.scan_limit, 0x1B);
+        assert_eq!(registers.power, 0x1C);
+        assert_eq!(registers.test, 0x01);
+        for command in RESYNC_ORDER {
+            let expected = match command as u8 {
+                0x0F => 0x01,
+                header => 0x10 + header,
+            };
+            assert_eq!(registers.value(command), expected);
+        }
+    }
+
+    #[test]
+    fn ignores_noop_and_unknown_registers() {
+        let mut registers = Registers::new();
+        for header in [0x00, 0x0D, 0x0E] {
+            registers.apply(header, 0xFF);
+        }
+
+        assert_eq!(registers, Registers::new());
+    }
+
+    #[test]
+    fn selects_by_low_nibble() {
+        let mut registers = Registers::new();
+        registers.apply(0xFA, 0x07);
+        registers.apply(0x31, 0x42);
+
+        assert_eq!(registers.intensity, 0x07);
+        assert_eq!(registers.digits[0], 0x42);
+    }
+}
@@ -0,0 +1,515 @@
+//! Long running display task driven by commands
+//!
+//! A [`DisplayService`] owns the driver and is meant to run as its own async
+//! task, receiving [`DisplayCommand`]s from other tasks through any channel
+//! implementing [`CommandReceiver`]. With the `embassy-sync` feature the
+//! receiver of an `embassy_sync` channel can be used directly.
+//!
+//! Commands are only taken between transfers, a transfer in progress is
+//! always completed. While an animation plays or a refresh is set up the
+//! service waits for either the next command or the end of the current frame
+//! or refresh interval, whichever comes first. The wait is split into steps
+//! of the service resolution, so a command arriving during a wait only drops
+//! the time of the step in progress. With an auto-sleep the displays are shut
+//! down while idle and woken up by the next command.
+
+use core::convert::Infallible;
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::task::Poll;
+use core::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::animation::{Animation, Player};
+use crate::connectors::Connector;
+use crate::framebuffer::FrameBuffer;
+use crate::refresh::RefreshPolicy;
+use crate::sleep::AutoSleep;
+use crate::{DataError, MAX_DIGITS, MAX7219, Target};
+
+/// Default length of the steps waits are split into
+const RESOLUTION: Duration = Duration::from_millis(10);
+
+///
+/// Command handled by a [`DisplayService`]
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DisplayCommand<'a> {
+    /// Stops any animation and writes text to a display, see `MAX7219::write_str`
+    ShowText {
+        /// Display to address as connected in series (0 -> last)
+        addr: usize,
+        /// Text to write
+        text: [u8; MAX_DIGITS],
+        /// Decimal points to enable by bitmask
+        dots: u8,
+    },
+    /// Sets intensity of one or all displays, animations keep playing
+    SetIntensity {
+        /// Display(s) to set intensity on
+        target: Target,
+        /// Intensity from `0x00` to `0x0F`
+        intensity: u8,
+    },
+    /// Replaces any animation and starts playing given one on a blank frame
+    PlayAnimation {
+        /// The animation to play
+        animation: Animation<'a>,
+        /// Column of the left animation edge
+        x: i32,
+        /// Row of the top animation edge
+        y: i32,
+    },
+    /// Stops the animation, leaving its current frame on the displays
+    StopAnimation,
+    /// Stops any animation and clears all displays
+    Clear,
+    /// Powers on all displays and resumes a paused animation
+    PowerOn,
+    /// Powers off all displays and pauses the animation
+    PowerOff,
+}
+
+///
+/// Receiving end of a command channel
+///
+#[allow(async_fn_in_trait)]
+pub trait CommandReceiver<'a> {
+    ///
+    /// Waits for the next command. The returned future must be cancellation
+    /// safe: dropping it before completion must not lose a command.
+    ///
+    async fn receive(&mut self) -> DisplayCommand<'a>;
+}
+
+#[cfg(feature = "embassy-sync")]
+impl<'a, M, const N: usize> CommandReceiver<'a>
+    for embassy_sync::channel::Receiver<'_, M, DisplayCommand<'a>, N>
+where
+    M: embassy_sync::blocking_mutex::raw::RawMutex,
+{
+    async fn receive(&mut self) -> DisplayCommand<'a> {
+        embassy_sync::channel::Receiver::receive(self).await
+    }
+}
+
+///
+/// Owns the driver and executes commands, timing animation frames itself
+///
+pub struct DisplayService<'a, const D: usize, CONNECTOR, DELAY> {
+    display: MAX7219<D, CONNECTOR>,
+    delay: DELAY,
+    framebuffer: FrameBuffer<D>,
+    player: Option<Player<'a>>,
+    powered: bool,
+    refresh: Option<RefreshPolicy>,
+    sleep: Option<AutoSleep<D>>,
+    resolution: Duration,
+}
+
+impl<'a, const D: usize, CONNECTOR, DELAY> DisplayService<'a, D, CONNECTOR, DELAY>
+where
+    CONNECTOR: Connector,
+    DELAY: DelayNs,
+{
+    ///
+    /// Construct a new service. The display should be initialized and
+    /// powered on by the caller.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `delay` - delay provider used to time animation frames
+    ///
+    pub fn new(display: MAX7219<D, CONNECTOR>, delay: DELAY) -> Self {
+        DisplayService {
+            display,
+            delay,
+            framebuffer: FrameBuffer::new(),
+            player: None,
+            powered: true,
+            refresh: None,
+            sleep: None,
+            resolution: RESOLUTION,
+        }
+    }
+
+    ///
+    /// Refreshes all registers from the driver state once per interval of the policy,
+    /// see [`RefreshPolicy`]. Time waited before a command arrived is counted
+    /// up to the resolution, see [`with_resolution`](Self::with_resolution).
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - the refresh interval to keep
+    ///
+    pub fn with_refresh(mut self, policy: RefreshPolicy) -> Self {
+        self.refresh = Some(policy);
+        self
+    }
+
+    ///
+    /// Shuts the displays down once no command was received and no animation
+    /// played for the timeout of the auto-sleep. The next command wakes them up.
+    ///
+    /// # Arguments
+    ///
+    /// * `sleep` - the idle timeout to keep
+    ///
+    pub fn with_auto_sleep(mut self, sleep: AutoSleep<D>) -> Self {
+        self.sleep = Some(sleep);
+        self
+    }
+
+    ///
+    /// Sets the length of the steps waits are split into, 10 ms by default.
+    /// A command arriving during a wait drops the time of the step in progress,
+    /// shorter steps keep animations and timeouts more accurate but wake up
+    /// the task more often.
+    ///
+    /// # Arguments
+    ///
+    /// * `resolution` - step length from 1 µs to `u32::MAX` µs
+    ///
+    pub fn with_resolution(mut self, resolution: Duration) -> Self {
+        self.resolution = resolution.clamp(
+            Duration::from_micros(1),
+            Duration::from_micros(u32::MAX as u64),
+        );
+        self
+    }
+
+    /// The driver owned by the service
+    pub fn display(&mut self) -> &mut MAX7219<D, CONNECTOR> {
+        &mut self.display
+    }
+
+    /// Whether an animation is playing
+    pub fn is_playing(&self) -> bool {
+        self.player.is_some_and(|player| !player.is_finished())
+    }
+
+    ///
+    /// Releases the driver and delay provider
+    ///
+    pub fn release(self) -> (MAX7219<D, CONNECTOR>, DELAY) {
+        (self.display, self.delay)
+    }
+
+    ///
+    /// Receives and handles commands until a transfer fails. The service
+    /// keeps its state so `run` can be called again after an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `receiver` - the channel to receive commands from
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn run<RECEIVER>(&mut self, receiver: &mut RECEIVER) -> Result<Infallible, DataError>
+    where
+        RECEIVER: CommandReceiver<'a>,
+    {
+        loop {
+            let frame = self.active_player().map(|player| player.remaining());
+            let refresh = self.refresh.map(|refresh| refresh.remaining());
+            let sleep = match frame {
+                Some(_) => None,
+                None => self.sleep.and_then(|sleep| sleep.remaining(&self.display)),
+            };
+
+            let wait = [frame, refresh, sleep].into_iter().flatten().min();
+
+            match wait {
+                Some(wait) => {
+                    let mut waited = Duration::ZERO;
+                    let delay = wait_steps(&mut self.delay, wait, self.resolution, &mut waited);
+                    match select(receiver.receive(), delay).await {
+                        Either::First(command) => {
+                            if !waited.is_zero() {
+                                self.elapse(waited).await?;
+                            }
+                            self.handle(command).await?
+                        }
+                        Either::Second(()) => self.elapse(waited).await?,
+                    }
+                }
+                None => self.handle(receiver.receive().await).await?,
+            }
+        }
+    }
+
+    ///
+    /// Handles a single command
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - the command to handle
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn handle(&mut self, command: DisplayCommand<'a>) -> Result<(), DataError> {
+        if let Some(sleep) = self.sleep.as_mut() {
+            match command {
+                // sets the power state itself
+                DisplayCommand::PowerOn | DisplayCommand::PowerOff => {
+                    sleep.reset(&mut self.display)
+                }
+                _ => {
+                    sleep.wake(&mut self.display).await?;
+                }
+            }
+        }
+
+        match command {
+            DisplayCommand::ShowText { addr, text, dots } => {
+                self.player = None;
+                self.display.write_str(addr, &text, dots).await
+            }
+            DisplayCommand::SetIntensity {
+                target: Target::Display(addr),
+                intensity,
+            } => self.display.set_intensity(addr, intensity).await,
+            DisplayCommand::SetIntensity {
+                target: Target::All,
+                intensity,
+            } => self.display.set_intensity_all(intensity).await,
+            DisplayCommand::PlayAnimation { animation, x, y } => {
+                self.player = Some(Player::new(animation, x, y));
+                // the displays may show anything written outside the frame buffer
+                self.framebuffer.invalidate();
+                self.draw_frame().await
+            }
+            DisplayCommand::StopAnimation => {
+                self.player = None;
+                Ok(())
+            }
+            DisplayCommand::Clear => {
+                self.player = None;
+                self.framebuffer.clear();
+                self.display.clear_all_displays().await
+            }
+            DisplayCommand::PowerOn => {
+                self.display.power_on().await?;
+                self.powered = true;
+                Ok(())
+            }
+            DisplayCommand::PowerOff => {
+                self.display.power_off().await?;
+                self.powered = false;
+                Ok(())
+            }
+        }
+    }
+
+    // advances animation and refresh by time waited without a command
+    async fn elapse(&mut self, elapsed: Duration) -> Result<(), DataError> {
+        let playing = self.active_player().is_some();
+        if let Some(player) = self.player.as_mut().filter(|_| playing)
+            && player.advance(elapsed)
+        {
+            self.draw_frame().await?;
+        }
+
+        if let Some(sleep) = self.sleep.as_mut() {
+            if playing {
+                sleep.touch();
+            } else {
+                sleep.tick(&mut self.display, elapsed).await?;
+            }
+        }
+
+        if let Some(refresh) = self.refresh.as_mut() {
+            refresh.tick(&mut self.display, elapsed).await?;
+        }
+
+        Ok(())
+    }
+
+    // the player while it is neither finished nor paused
+    fn active_player(&self) -> Option<Player<'a>> {
+        self.player
+            .filter(|player| self.powered && !player.is_finished())
+    }
+
+    async fn draw_frame(&mut self) -> Result<(), DataError> {
+        if let Some(player) = self.player {
+            self.framebuffer.clear();
+            player.draw(&mut self.framebuffer);
+            self.framebuffer.flush(&mut self.display).await?;
+        }
+
+        Ok(())
+    }
+}
+
+// waits in steps of at most `resolution`, adding each completed step to `waited`
+async fn wait_steps<DELAY: DelayNs>(
+    delay: &mut DELAY,
+    duration: Duration,
+    resolution: Duration,
+    waited: &mut Duration,
+) {
+    while *waited < duration {
+        let step = (duration - *waited).min(resolution);
+        delay.delay_us(step.as_micros() as u32).await;
+        *waited += step;
+    }
+}
+
+enum Either<A, B> {
+    First(A),
+    Second(B),
+}
+
+// polls both futures until one completes, the other one is dropped
+async fn select<A, B>(first: A, second: B) -> Either<A::Output, B::Output>
+where
+    A: Future,
+    B: Future,
+{
+    let mut first = pin!(first);
+    let mut second = pin!(second);
+
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = first.as_mut().poll(cx) {
+            return Poll::Ready(Either::First(output));
+        }
+        if let Poll::Ready(output) = second.as_mut().poll(cx) {
+            return Poll::Ready(Either::Second(output));
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::animation::{AnimationFrame, Sprite};
+    use crate::testing::{Clock, Recorder, block_on, poll_until, recorder, take};
+    use core::cell::Cell;
+
+    const SPRITE: Sprite = Sprite::new(1, 1, &[0x80]);
+
+    // receiver yielding a command once per period of the simulated clock
+    struct Periodic<'c> {
+        now: &'c Cell<Duration>,
+        period: Duration,
+        next: Duration,
+        received: usize,
+    }
+
+    impl<'c> Periodic<'c> {
+        fn new(now: &'c Cell<Duration>, period: Duration) -> Self {
+            Periodic {
+                now,
+                period,
+                next: period,
+                received: 0,
+            }
+        }
+    }
+
+    impl CommandReceiver<'static> for Periodic<'_> {
+        async fn receive(&mut self) -> DisplayCommand<'static> {
+            poll_fn(|_| {
+                if self.now.get() < self.next {
+                    return Poll::Pending;
+                }
+                self.next += self.period;
+                self.received += 1;
+                Poll::Ready(DisplayCommand::SetIntensity {
+                    target: Target::All,
+                    intensity: 0x08,
+                })
+            })
+            .await
+        }
+    }
+
+    fn service(now: &Cell<Duration>) -> DisplayService<'static, 1, Recorder, Clock<'_>> {
+        let clock = Clock {
+            now,
+            step: Duration::from_millis(1),
+        };
+        DisplayService::new(recorder::<1>(), clock).with_resolution(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn counts_time_waited_before_commands() {
+        static FRAMES: [AnimationFrame; 2] = [
+            AnimationFrame::new(SPRITE, Duration::from_millis(10)),
+            AnimationFrame::new(SPRITE, Duration::from_millis(40)),
+        ];
+        let now = Cell::new(Duration::ZERO);
+        let mut service = service(&now);
+        let mut receiver = Periodic::new(&now, Duration::from_millis(3));
+        block_on(service.handle(DisplayCommand::PlayAnimation {
+            animation: Animation::new(&FRAMES),
+            x: 0,
+            y: 0,
+        }))
+        .unwrap();
+
+        // a command arriving in a step drops at most that step
+        poll_until(service.run(&mut receiver), || {
+            now.get() >= Duration::from_millis(20)
+        });
+
+        assert!(receiver.received >= 6);
+        let player = service.player.unwrap();
+        assert_eq!(player.frame().duration, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn refreshes_under_frequent_commands() {
+        let now = Cell::new(Duration::ZERO);
+        let step = Duration::from_micros(100);
+        let clock = Clock { now: &now, step };
+        let mut service = DisplayService::new(recorder::<1>(), clock)
+            .with_resolution(step)
+            .with_refresh(RefreshPolicy::new(Duration::from_millis(10)));
+        let mut receiver = Periodic::new(&now, Duration::from_millis(3));
+
+        // 11 commands drop one 100 µs step each, 33.9 ms of 35 ms count
+        poll_until(service.run(&mut receiver), || {
+            now.get() >= Duration::from_millis(35)
+        });
+
+        let refreshes = take(service.display())
+            .iter()
+            .filter(|transfer| transfer[0] == Command::ScanLimit as u8)
+            .count();
+        assert_eq!(receiver.received, 11);
+        assert_eq!(refreshes, 3);
+    }
+
+    #[test]
+    fn wakes_on_command() {
+        let now = Cell::new(Duration::ZERO);
+        let sleep = AutoSleep::new(Duration::from_millis(10));
+        // the refresh keeps the service waiting on the clock while asleep
+        let mut service = service(&now)
+            .with_auto_sleep(sleep)
+            .with_refresh(RefreshPolicy::new(Duration::from_secs(60)));
+        block_on(service.display().power_on()).unwrap();
+        let mut receiver = Periodic::new(&now, Duration::from_millis(15));
+
+        poll_until(service.run(&mut receiver), || {
+            now.get() >= Duration::from_millis(16)
+        });
+
+        let power = Command::Power as u8;
+        let transfers = take(service.display());
+        assert_eq!(receiver.received, 1);
+        assert!(transfers.contains(&[power, 0].to_vec()));
+        assert!(service.display().is_powered(0));
+    }
+}
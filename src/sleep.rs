@@ -0,0 +1,244 @@
+//! Automatic shutdown of idle displays
+//!
+//! In shutdown the MAX7219 blanks its outputs but keeps all registers, so
+//! waking up only rewrites the power register and the previous content shows
+//! again right away without a redraw.
+//!
+//! The driver keeps the power state of displays shut down by an [`AutoSleep`]
+//! and restores it with the next write, in a transfer of its own right before
+//! the data. [`AutoSleep::wake`] wakes the displays without writing anything
+//! else, a [`DisplayService`](crate::DisplayService) does so for every command.
+
+use core::time::Duration;
+
+use crate::connectors::Connector;
+use crate::{DataError, MAX7219};
+
+///
+/// Shuts all displays down after an idle period. The next write
+/// through the driver restores their power state.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AutoSleep<const D: usize> {
+    timeout: Duration,
+    idle: Duration,
+    // whether the displays were shut down by this auto-sleep
+    slept: bool,
+}
+
+impl<const D: usize> AutoSleep<D> {
+    ///
+    /// Construct a new auto-sleep, awake with no idle time
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - idle time after which the displays are shut down
+    ///
+    pub const fn new(timeout: Duration) -> Self {
+        AutoSleep {
+            timeout,
+            idle: Duration::ZERO,
+            slept: false,
+        }
+    }
+
+    /// Idle time after which the displays are shut down
+    pub const fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    ///
+    /// Time until the displays are shut down, `None` while asleep
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver the displays are shut down through
+    ///
+    pub fn remaining<CONNECTOR>(&self, display: &MAX7219<D, CONNECTOR>) -> Option<Duration> {
+        (!display.is_asleep()).then(|| self.timeout.saturating_sub(self.idle))
+    }
+
+    ///
+    /// Restarts the idle period without writing, for activity which keeps
+    /// the displays awake such as a playing animation
+    ///
+    pub fn touch(&mut self) {
+        self.idle = Duration::ZERO;
+    }
+
+    ///
+    /// Forgets a sleep and restarts the idle period, for when the power
+    /// state is set by other means. The next write does not wake the displays.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver the displays were shut down through
+    ///
+    pub fn reset<CONNECTOR>(&mut self, display: &mut MAX7219<D, CONNECTOR>) {
+        self.idle = Duration::ZERO;
+        self.slept = false;
+        display.sleeping = None;
+    }
+
+    ///
+    /// Advances the idle time and shuts all displays down in a single
+    /// transfer once the timeout is reached. Returns whether they were shut down.
+    /// Displays woken up by a write since restart the idle period.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to shut down
+    /// * `elapsed` - time since the previous call
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn tick<CONNECTOR>(
+        &mut self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        elapsed: Duration,
+    ) -> Result<bool, DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        if display.is_asleep() {
+            return Ok(false);
+        }
+        if self.slept {
+            self.touch();
+            self.slept = false;
+        }
+
+        self.idle = self.idle.saturating_add(elapsed);
+        if self.idle < self.timeout {
+            return Ok(false);
+        }
+
+        let states = core::array::from_fn(|addr| display.is_powered(addr));
+        display.power_off().await?;
+        display.sleeping = Some(states);
+        self.slept = true;
+
+        Ok(true)
+    }
+
+    ///
+    /// Restarts the idle period and, when asleep, restores the power state of
+    /// all displays in a single transfer. Returns whether the displays were woken up.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to wake up
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer,
+    ///   the displays stay asleep
+    ///
+    pub async fn wake<CONNECTOR>(
+        &mut self,
+        display: &mut MAX7219<D, CONNECTOR>,
+    ) -> Result<bool, DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        self.touch();
+        let woken = display.wake().await?;
+        self.slept = false;
+
+        Ok(woken)
+    }
+}
+
+impl<const D: usize, CONNECTOR> MAX7219<D, CONNECTOR> {
+    /// Whether an [`AutoSleep`] shut the displays down and no write woke them up since
+    pub fn is_asleep(&self) -> bool {
+        self.sleeping.is_some()
+    }
+}
+
+impl<const D: usize, CONNECTOR> MAX7219<D, CONNECTOR>
+where
+    CONNECTOR: Connector,
+{
+    // restores the power state from before the sleep, returns whether the displays slept
+    pub(crate) async fn wake(&mut self) -> Result<bool, DataError> {
+        let Some(states) = self.sleeping else {
+            return Ok(false);
+        };
+
+        self.send(Self::power_buffers(&states).as_flattened())
+            .await?;
+        self.sleeping = None;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::testing::{block_on, recorder, take};
+
+    #[test]
+    fn sleeps_after_timeout_and_wakes() {
+        let mut display = recorder::<2>();
+        block_on(display.set_power(1, true)).unwrap();
+        let mut sleep = AutoSleep::new(Duration::from_secs(10));
+        let mut tick = |display: &mut _, secs| {
+            block_on(sleep.tick(display, Duration::from_secs(secs))).unwrap()
+        };
+
+        assert!(!tick(&mut display, 6));
+        assert!(tick(&mut display, 4));
+        assert!(!display.is_powered(1));
+
+        assert!(block_on(sleep.wake(&mut display)).unwrap());
+        assert!(!display.is_powered(0) && display.is_powered(1));
+        assert_eq!(sleep.remaining(&display), Some(Duration::from_secs(10)));
+        assert!(!block_on(sleep.wake(&mut display)).unwrap());
+    }
+
+    #[test]
+    fn wakes_on_next_write() {
+        let mut display = recorder::<2>();
+        block_on(display.set_power(1, true)).unwrap();
+        let mut sleep = AutoSleep::new(Duration::from_secs(10));
+        assert!(block_on(sleep.tick(&mut display, Duration::from_secs(10))).unwrap());
+        assert!(display.is_asleep());
+        assert_eq!(sleep.remaining(&display), None);
+        take(&mut display);
+
+        let intensity = Command::Intensity as u8;
+        block_on(display.write_raw_bytes(&[intensity, 0x03, intensity, 0x04])).unwrap();
+        block_on(display.write_frame(&[[0xFF; 8]; 2])).unwrap();
+
+        let power = Command::Power as u8;
+        let transfers = take(&mut display);
+        assert_eq!(transfers[0], [power, 0x00, power, 0x01]);
+        assert_eq!(transfers[1], [intensity, 0x03, intensity, 0x04]);
+        assert!(!transfers[2..].iter().any(|t| t[0] == power));
+        assert!(!display.is_asleep() && display.is_powered(1));
+
+        // the idle period restarts after the wake
+        assert!(!block_on(sleep.tick(&mut display, Duration::from_secs(9))).unwrap());
+        assert!(block_on(sleep.tick(&mut display, Duration::from_secs(1))).unwrap());
+    }
+
+    #[test]
+    fn stays_asleep_after_reset() {
+        let mut display = recorder::<1>();
+        block_on(display.power_on()).unwrap();
+        let mut sleep = AutoSleep::new(Duration::ZERO);
+        assert!(block_on(sleep.tick(&mut display, Duration::ZERO)).unwrap());
+
+        sleep.reset(&mut display);
+        take(&mut display);
+        block_on(display.write_raw_bytes(&[Command::Intensity as u8, 0x03])).unwrap();
+
+        assert_eq!(take(&mut display), [[Command::Intensity as u8, 0x03]]);
+        assert!(!display.is_powered(0));
+    }
+}
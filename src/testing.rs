@@ -0,0 +1,244 @@
+//! Helpers shared by the unit tests
+
+extern crate std;
+
+use core::cell::{Cell, RefCell};
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use std::vec::Vec;
+
+use embedded_hal::digital::{self, OutputPin};
+use embedded_hal::spi::{self, Operation};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiBus;
+
+use crate::connectors::Connector;
+use crate::{DataError, MAX7219};
+
+/// Connector recording every transfer, failing `fail` ones after the next `pass`
+#[derive(Default)]
+pub(crate) struct Recorder {
+    pub transfers: Vec<Vec<u8>>,
+    pub pass: usize,
+    pub fail: usize,
+}
+
+impl Connector for Recorder {
+    async fn write_raw_bytes(&mut self, bytes: &[u8]) -> Result<(), DataError> {
+        if self.pass > 0 {
+            self.pass -= 1;
+        } else if self.fail > 0 {
+            self.fail -= 1;
+            return Err(DataError::Spi);
+        }
+
+        self.transfers.push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// Driver writing to a fresh `Recorder`
+pub(crate) fn recorder<const D: usize>() -> MAX7219<D, Recorder> {
+    MAX7219::new(Recorder::default())
+}
+
+/// Transfers recorded so far, emptying the record
+pub(crate) fn take<const D: usize>(display: &mut MAX7219<D, Recorder>) -> Vec<Vec<u8>> {
+    core::mem::take(&mut display.connector.transfers)
+}
+
+/// Runs a future to completion, the futures under test never wait on wakers
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+/// Delay advancing a simulated clock by at most `step` per poll
+pub(crate) struct Clock<'a> {
+    pub now: &'a Cell<Duration>,
+    pub step: Duration,
+}
+
+impl DelayNs for Clock<'_> {
+    async fn delay_ns(&mut self, ns: u32) {
+        let end = self.now.get() + Duration::from_nanos(ns.into());
+        poll_fn(|_| {
+            let now = self.now.get();
+            if now >= end {
+                return Poll::Ready(());
+            }
+            self.now.set(end.min(now + self.step));
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Polls a future until a condition holds, the future must keep waiting until then
+pub(crate) fn poll_until<F: Future>(future: F, mut done: impl FnMut() -> bool) {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+
+    for _ in 0..100_000 {
+        if done() {
+            return;
+        }
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+    }
+
+    panic!("condition not reached");
+}
+
+/// Delay recording each wait in nanoseconds without waiting
+#[derive(Default)]
+pub(crate) struct Waits(pub Vec<u32>);
+
+impl Waits {
+    /// Sum of all waits
+    pub fn total(&self) -> Duration {
+        self.0
+            .iter()
+            .map(|ns| Duration::from_nanos((*ns).into()))
+            .sum()
+    }
+}
+
+impl DelayNs for Waits {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.0.push(ns);
+    }
+}
+
+/// Pin level change, delay or bus access recorded by the logging mocks
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Event {
+    Pin(&'static str, bool),
+    Delay(u32),
+    Write(Vec<u8>),
+    Flush,
+}
+
+/// Events shared by the logging mocks in order
+pub(crate) type Log = RefCell<Vec<Event>>;
+
+/// Output pin logging level changes, failing every change when `fail` is set
+pub(crate) struct LogPin<'a> {
+    pub name: &'static str,
+    pub log: &'a Log,
+    pub fail: bool,
+}
+
+impl<'a> LogPin<'a> {
+    pub fn new(name: &'static str, log: &'a Log) -> Self {
+        LogPin {
+            name,
+            log,
+            fail: false,
+        }
+    }
+
+    fn set(&mut self, high: bool) -> Result<(), digital::ErrorKind> {
+        if self.fail {
+            return Err(digital::ErrorKind::Other);
+        }
+        self.log.borrow_mut().push(Event::Pin(self.name, high));
+        Ok(())
+    }
+}
+
+impl digital::ErrorType for LogPin<'_> {
+    type Error = digital::ErrorKind;
+}
+
+impl OutputPin for LogPin<'_> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set(false)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set(true)
+    }
+}
+
+/// Delay logging each wait in nanoseconds without waiting
+pub(crate) struct LogDelay<'a>(pub &'a Log);
+
+impl DelayNs for LogDelay<'_> {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.0.borrow_mut().push(Event::Delay(ns));
+    }
+}
+
+/// SPI bus and blocking SPI device logging writes and flushes,
+/// failing every access when `fail` is set. The device wraps
+/// its transactions in `cs` pin events.
+pub(crate) struct LogSpi<'a> {
+    pub log: &'a Log,
+    pub fail: bool,
+}
+
+impl<'a> LogSpi<'a> {
+    pub fn new(log: &'a Log) -> Self {
+        LogSpi { log, fail: false }
+    }
+
+    fn push(&mut self, event: Event) -> Result<(), spi::ErrorKind> {
+        if self.fail {
+            return Err(spi::ErrorKind::Other);
+        }
+        self.log.borrow_mut().push(event);
+        Ok(())
+    }
+}
+
+impl spi::ErrorType for LogSpi<'_> {
+    type Error = spi::ErrorKind;
+}
+
+impl SpiBus<u8> for LogSpi<'_> {
+    async fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        words.fill(0x00);
+        Ok(())
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.push(Event::Write(words.to_vec()))
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        read.fill(0x00);
+        self.push(Event::Write(write.to_vec()))
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.push(Event::Write(words.to_vec()))
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.push(Event::Flush)
+    }
+}
+
+impl spi::SpiDevice<u8> for LogSpi<'_> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.push(Event::Pin("cs", false))?;
+        for operation in operations {
+            match operation {
+                Operation::Write(words) => self.push(Event::Write(words.to_vec()))?,
+                Operation::Transfer(_, words) => self.push(Event::Write(words.to_vec()))?,
+                Operation::TransferInPlace(words) => self.push(Event::Write(words.to_vec()))?,
+                Operation::Read(_) | Operation::DelayNs(_) => {}
+            }
+        }
+        self.push(Event::Flush)?;
+        self.push(Event::Pin("cs", true))
+    }
+}
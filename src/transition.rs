@@ -0,0 +1,383 @@
+//! Screen transitions between whole chain frames
+
+use core::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::connectors::Connector;
+use crate::fade::delay_us;
+use crate::framebuffer::FrameBuffer;
+use crate::{DataError, MAX_DIGITS, MAX7219, Target};
+
+/// Number of steps a dissolve is split into
+const DISSOLVE_STEPS: usize = 16;
+
+/// Kinds of transition between two frames
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Transition {
+    /// The new frame pushes the old one out to the left, one column per step
+    SlideLeft,
+    /// The new frame pushes the old one out to the top, one row per step
+    SlideUp,
+    /// The new frame is uncovered from left to right, one column per step
+    Wipe,
+    /// The new frame replaces the old one pixel by pixel in a fixed pseudo-random order
+    Dissolve,
+    /// Intensity of all displays fades down, the frame swaps while the displays
+    /// are shut down and intensity fades back up to where each display was.
+    /// Fades out of whatever the displays show, the `from` frame is not used.
+    Fade,
+}
+
+impl<const D: usize> FrameBuffer<D> {
+    ///
+    /// Transitions the displays from one frame to another. The frame buffer
+    /// holds the new frame afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `from` - raw display bytes shown at the start, unused by [`Transition::Fade`]
+    /// * `to` - raw display bytes shown at the end
+    /// * `transition` - the kind of transition
+    /// * `duration` - time the whole transition takes
+    /// * `delay` - delay provider used to time the steps
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn transition<CONNECTOR, DELAY>(
+        &mut self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        from: &[[u8; MAX_DIGITS]; D],
+        to: &[[u8; MAX_DIGITS]; D],
+        transition: Transition,
+        duration: Duration,
+        delay: &mut DELAY,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+        DELAY: DelayNs,
+    {
+        if transition == Transition::Fade {
+            let intensities = core::array::from_fn(|addr| display.intensity(addr));
+            let base = display.target_intensity(Target::All);
+            let half = duration / 2;
+
+            display.fade_to(Target::All, 0, half, delay).await?;
+
+            // intensity 0 still lights the LEDs, the swap happens in shutdown
+            let powered = core::array::from_fn(|addr| display.is_powered(addr));
+            display.power_off().await?;
+            self.set_frame(to);
+            self.flush(display).await?;
+            display.set_power_each(&powered).await?;
+
+            display.fade_to(Target::All, base, half, delay).await?;
+            // displays set apart from the chain get their own intensity back
+            return display.restore_intensities(&intensities).await;
+        }
+
+        let width = D * 8;
+        let steps = match transition {
+            Transition::SlideLeft | Transition::Wipe => width,
+            Transition::SlideUp => MAX_DIGITS,
+            _ => DISSOLVE_STEPS,
+        };
+        let step_us = (duration.as_micros() / steps.max(1) as u128).min(u64::MAX as u128) as u64;
+
+        self.set_frame(from);
+        self.flush(display).await?;
+
+        let rotation = self.rotation();
+        let pixel = |frame: &[[u8; MAX_DIGITS]; D], x, y| rotation.pixel(frame, x, y);
+        let pixels = width * MAX_DIGITS;
+        let mut order = Dissolve::new(pixels);
+
+        for step in 1..=steps {
+            match transition {
+                Transition::SlideLeft => {
+                    for y in 0..MAX_DIGITS {
+                        for x in 0..width {
+                            let on = if x + step < width {
+                                pixel(from, x + step, y)
+                            } else {
+                                pixel(to, x + step - width, y)
+                            };
+                            self.set_pixel(x, y, on);
+                        }
+                    }
+                }
+                Transition::SlideUp => {
+                    for y in 0..MAX_DIGITS {
+                        for x in 0..width {
+                            let on = if y + step < MAX_DIGITS {
+                                pixel(from, x, y + step)
+                            } else {
+                                pixel(to, x, y + step - MAX_DIGITS)
+                            };
+                            self.set_pixel(x, y, on);
+                        }
+                    }
+                }
+                Transition::Wipe => {
+                    for y in 0..MAX_DIGITS {
+                        self.set_pixel(step - 1, y, pixel(to, step - 1, y));
+                    }
+                }
+                _ => {
+                    // spread pixels evenly, the last step takes the remainder
+                    let count = pixels * step / steps - pixels * (step - 1) / steps;
+                    for index in order.by_ref().take(count) {
+                        let (x, y) = (index % width, index / width);
+                        self.set_pixel(x, y, pixel(to, x, y));
+                    }
+                }
+            }
+
+            self.flush(display).await?;
+            delay_us(delay, step_us).await;
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// Visits every index below `len` exactly once in a fixed pseudo-random order
+/// using a full period linear congruential generator over the next power of two.
+///
+struct Dissolve {
+    len: usize,
+    modulus: usize,
+    state: usize,
+    visited: usize,
+}
+
+impl Dissolve {
+    fn new(len: usize) -> Self {
+        Dissolve {
+            len,
+            modulus: len.next_power_of_two(),
+            state: 0,
+            visited: 0,
+        }
+    }
+}
+
+impl Iterator for Dissolve {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.visited < self.modulus {
+            // c odd and a - 1 divisible by 4 gives a full period modulo powers of two
+            self.state = (self
+                .state
+                .wrapping_mul(1_664_525)
+                .wrapping_add(1_013_904_223))
+                & (self.modulus - 1);
+            self.visited += 1;
+            if self.state < self.len {
+                return Some(self.state);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::connectors::NoDelay;
+    use crate::testing::{Waits, block_on, recorder, take};
+
+    extern crate std;
+    use std::vec::Vec;
+
+    // values written to one digit register of the first display, in order
+    fn rows(transfers: &[Vec<u8>], register: u8) -> Vec<u8> {
+        transfers
+            .iter()
+            .filter(|t| t[0] == register)
+            .map(|t| t[1])
+            .collect()
+    }
+
+    #[test]
+    fn slides_left_column_by_column() {
+        let mut display = recorder::<1>();
+        let mut framebuffer = FrameBuffer::<1>::new();
+        let mut delay = Waits::default();
+
+        let slide = framebuffer.transition(
+            &mut display,
+            &[[0x01; MAX_DIGITS]],
+            &[[0x80; MAX_DIGITS]],
+            Transition::SlideLeft,
+            Duration::from_millis(8),
+            &mut delay,
+        );
+        block_on(slide).unwrap();
+
+        assert_eq!(
+            rows(&take(&mut display), 0x01),
+            [0x01, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xC0, 0x80]
+        );
+        assert_eq!(framebuffer.frame(), &[[0x80; MAX_DIGITS]]);
+        assert_eq!(delay.total(), Duration::from_millis(8));
+    }
+
+    #[test]
+    fn slides_up_row_by_row() {
+        let mut display = recorder::<1>();
+        let mut framebuffer = FrameBuffer::<1>::new();
+        let mut from = [[0x00; MAX_DIGITS]];
+        from[0][7] = 0xFF;
+        let mut to = [[0x00; MAX_DIGITS]];
+        to[0][0] = 0x0F;
+
+        let mut delay = NoDelay;
+        let slide = framebuffer.transition(
+            &mut display,
+            &from,
+            &to,
+            Transition::SlideUp,
+            Duration::ZERO,
+            &mut delay,
+        );
+        block_on(slide).unwrap();
+
+        let transfers = take(&mut display);
+        assert_eq!(rows(&transfers, 0x01), [0x00, 0xFF, 0x0F]);
+        assert_eq!(rows(&transfers, 0x08), [0xFF, 0x0F, 0x00]);
+        assert_eq!(framebuffer.frame(), &to);
+    }
+
+    #[test]
+    fn wipes_column_by_column() {
+        let mut display = recorder::<2>();
+        let mut framebuffer = FrameBuffer::<2>::new();
+
+        let mut delay = NoDelay;
+        let wipe = framebuffer.transition(
+            &mut display,
+            &[[0xFF; MAX_DIGITS]; 2],
+            &[[0x00; MAX_DIGITS]; 2],
+            Transition::Wipe,
+            Duration::ZERO,
+            &mut delay,
+        );
+        block_on(wipe).unwrap();
+
+        let row: Vec<[u8; 2]> = take(&mut display)
+            .iter()
+            .filter(|t| t[0] == 0x01)
+            .map(|t| [t[1], t[3]])
+            .collect();
+        assert_eq!(row.len(), 17);
+        assert_eq!(row[1], [0x7F, 0xFF]);
+        assert_eq!(row[8], [0x00, 0xFF]);
+        assert_eq!(row[9], [0x00, 0x7F]);
+        assert_eq!(row[16], [0x00, 0x00]);
+    }
+
+    #[test]
+    fn dissolves_over_long_steps() {
+        let mut display = recorder::<2>();
+        let mut framebuffer = FrameBuffer::<2>::new();
+        let mut delay = Waits::default();
+
+        // steps longer than u32::MAX microseconds
+        let duration = Duration::from_secs(DISSOLVE_STEPS as u64 * 5_000);
+        let dissolve = framebuffer.transition(
+            &mut display,
+            &[[0x00; MAX_DIGITS]; 2],
+            &[[0xFF; MAX_DIGITS]; 2],
+            Transition::Dissolve,
+            duration,
+            &mut delay,
+        );
+        block_on(dissolve).unwrap();
+
+        assert_eq!(framebuffer.frame(), &[[0xFF; MAX_DIGITS]; 2]);
+        assert_eq!(delay.total(), duration);
+    }
+
+    #[test]
+    fn dissolve_visits_every_pixel_once() {
+        for len in (0..=200).chain([512, 1000]) {
+            let mut seen = std::vec![false; len];
+            for index in Dissolve::new(len) {
+                assert!(!seen[index], "{index} of {len} visited twice");
+                seen[index] = true;
+            }
+            assert!(seen.iter().all(|s| *s), "{len} not covered");
+        }
+    }
+
+    #[test]
+    fn fade_swaps_in_shutdown() {
+        let mut display = recorder::<2>();
+        let mut framebuffer = FrameBuffer::<2>::new();
+        block_on(async {
+            display.set_power(1, true).await.unwrap();
+            display.set_intensity_all(3).await.unwrap();
+        });
+        take(&mut display);
+
+        let to = [[0xFF; MAX_DIGITS]; 2];
+        let mut delay = NoDelay;
+        let fade = framebuffer.transition(
+            &mut display,
+            &[[0x00; MAX_DIGITS]; 2],
+            &to,
+            Transition::Fade,
+            Duration::ZERO,
+            &mut delay,
+        );
+        block_on(fade).unwrap();
+
+        let transfers = take(&mut display);
+        let power = Command::Power as u8;
+        let off = transfers
+            .iter()
+            .position(|t| *t == [power, 0, power, 0])
+            .unwrap();
+        let on = transfers
+            .iter()
+            .position(|t| *t == [power, 0, power, 1])
+            .unwrap();
+        let rows = transfers.iter().position(|t| t[0] == 0x01).unwrap();
+        assert!(off < rows && rows < on);
+        assert!(!display.is_powered(0) && display.is_powered(1));
+        assert_eq!(display.intensity(1), 3);
+    }
+
+    #[test]
+    fn fade_restores_calibrated_intensities() {
+        let mut display = recorder::<3>();
+        let mut framebuffer = FrameBuffer::<3>::new();
+        display.set_intensity_offsets([2, -1, 0]);
+        block_on(async {
+            display.set_intensity_all(5).await.unwrap();
+            display.set_intensity(2, 9).await.unwrap();
+        });
+
+        let mut delay = NoDelay;
+        let fade = framebuffer.transition(
+            &mut display,
+            &[[0x00; MAX_DIGITS]; 3],
+            &[[0xFF; MAX_DIGITS]; 3],
+            Transition::Fade,
+            Duration::ZERO,
+            &mut delay,
+        );
+        block_on(fade).unwrap();
+
+        let intensities: [u8; 3] = core::array::from_fn(|addr| display.intensity(addr));
+        assert_eq!(intensities, [7, 4, 9]);
+    }
+}
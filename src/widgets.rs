@@ -0,0 +1,442 @@
+//! Bar graph and level meter widgets
+//!
+//! A [`Level`] keeps track of a value within a range together with its
+//! held peak and the minimum and maximum seen. It is rendered by
+//! [`BarGraph`] on chained 8x8 matrices or by [`SegmentMeter`] on seven segment digits.
+
+use crate::connectors::Connector;
+use crate::{DataError, DigitWindow, MAX_DIGITS, MAX7219};
+
+/// Segments E and F, the left half of a digit
+const SEGMENTS_LEFT: u8 = 0b0000_0110;
+/// Segments B and C, the right half of a digit
+const SEGMENTS_RIGHT: u8 = 0b0011_0000;
+
+/// Direction in which a bar grows
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Orientation {
+    /// Grows from the leftmost column of display 0 towards the last display
+    Horizontal,
+    /// Grows from the bottom row (digit 7) upwards over the whole width
+    Vertical,
+}
+
+///
+/// A value within a range with peak hold and min/max tracking.
+/// Each call to `update` counts as one tick for the peak hold.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Level {
+    low: i32,
+    high: i32,
+    value: i32,
+    peak: i32,
+    peak_age: u16,
+    peak_hold: u16,
+    min: i32,
+    max: i32,
+    /// Show a marker at the held peak
+    pub show_peak: bool,
+    /// Show markers at the minimum and maximum seen
+    pub show_min_max: bool,
+}
+
+impl Level {
+    ///
+    /// Construct a new level for values in `low..=high`
+    ///
+    /// # Arguments
+    ///
+    /// * `low` - value shown as an empty bar
+    /// * `high` - value shown as a full bar
+    ///
+    /// # Panics
+    ///
+    /// * if `low` is not smaller than `high`
+    ///
+    pub const fn new(low: i32, high: i32) -> Self {
+        assert!(low < high, "empty level range");
+
+        Level {
+            low,
+            high,
+            value: low,
+            peak: low,
+            peak_age: 0,
+            peak_hold: 0,
+            min: high,
+            max: low,
+            show_peak: false,
+            show_min_max: false,
+        }
+    }
+
+    ///
+    /// Enables the peak marker held for given number of updates
+    /// before it falls back to the current value
+    ///
+    /// # Arguments
+    ///
+    /// * `ticks` - number of `update` calls to hold a peak for
+    ///
+    pub const fn with_peak_hold(mut self, ticks: u16) -> Self {
+        self.peak_hold = ticks;
+        self.show_peak = true;
+        self
+    }
+
+    ///
+    /// Enables the minimum and maximum markers
+    ///
+    pub const fn with_min_max(mut self) -> Self {
+        self.show_min_max = true;
+        self
+    }
+
+    ///
+    /// Sets a new value, advancing the peak hold by one tick
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - the new value, clamped to the level range
+    ///
+    pub fn update(&mut self, value: i32) {
+        let value = value.clamp(self.low, self.high);
+        self.value = value;
+
+        if value >= self.peak || self.peak_age >= self.peak_hold {
+            self.peak = value;
+            self.peak_age = 0;
+        } else {
+            self.peak_age += 1;
+        }
+
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    ///
+    /// Forgets the minimum and maximum seen so far
+    ///
+    pub fn reset_min_max(&mut self) {
+        self.min = self.high;
+        self.max = self.low;
+    }
+
+    /// Current value
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    /// Held peak value
+    pub fn peak(&self) -> i32 {
+        self.peak
+    }
+
+    /// Minimum value seen, if any
+    pub fn min(&self) -> Option<i32> {
+        (self.min <= self.max).then_some(self.min)
+    }
+
+    /// Maximum value seen, if any
+    pub fn max(&self) -> Option<i32> {
+        (self.min <= self.max).then_some(self.max)
+    }
+
+    ///
+    /// Number of steps lit for the current value out of `steps`
+    ///
+    pub fn fill(&self, steps: usize) -> usize {
+        self.scale(self.value, steps)
+    }
+
+    ///
+    /// Calls `mark` with the step index of every enabled marker out of `steps`.
+    /// Markers at the empty end of the range are not shown.
+    ///
+    pub fn markers(&self, steps: usize, mut mark: impl FnMut(usize)) {
+        let mut marker = |value: i32| {
+            if let Some(step) = self.scale(value, steps).checked_sub(1) {
+                mark(step);
+            }
+        };
+
+        if self.show_peak {
+            marker(self.peak);
+        }
+        if self.show_min_max && self.min <= self.max {
+            marker(self.min);
+            marker(self.max);
+        }
+    }
+
+    // scales value into 0..=steps
+    fn scale(&self, value: i32, steps: usize) -> usize {
+        let offset = (value.clamp(self.low, self.high) as i64 - self.low as i64) as u64;
+        let range = (self.high as i64 - self.low as i64) as u64;
+
+        (offset * steps as u64 / range) as usize
+    }
+}
+
+///
+/// Bar graph over all chained 8x8 matrix displays
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BarGraph {
+    /// The value shown by the bar
+    pub level: Level,
+    /// Direction in which the bar grows
+    pub orientation: Orientation,
+}
+
+impl BarGraph {
+    ///
+    /// Construct a new bar graph
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - the value shown by the bar
+    /// * `orientation` - direction in which the bar grows
+    ///
+    pub const fn new(level: Level, orientation: Orientation) -> Self {
+        BarGraph { level, orientation }
+    }
+
+    ///
+    /// Renders the bar into a frame of raw display bytes as used by `write_frame`.
+    /// Columns run from bit 7 of display 0 to bit 0 of display `D - 1`.
+    ///
+    pub fn render<const D: usize>(&self, frame: &mut [[u8; MAX_DIGITS]; D]) {
+        for raw in frame.iter_mut() {
+            *raw = [0x00; MAX_DIGITS];
+        }
+
+        match self.orientation {
+            Orientation::Horizontal => {
+                let steps = D * 8;
+                let mut light = |column: usize| {
+                    for row in frame[column / 8].iter_mut() {
+                        *row |= 0b1000_0000 >> (column % 8);
+                    }
+                };
+
+                (0..self.level.fill(steps)).for_each(&mut light);
+                self.level.markers(steps, light);
+            }
+            Orientation::Vertical => {
+                let mut light = |step: usize| {
+                    for raw in frame.iter_mut() {
+                        raw[MAX_DIGITS - 1 - step] = 0xFF;
+                    }
+                };
+
+                (0..self.level.fill(MAX_DIGITS)).for_each(&mut light);
+                self.level.markers(MAX_DIGITS, light);
+            }
+        }
+    }
+
+    ///
+    /// Renders and writes the bar to all displays
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let mut frame = [[0x00; MAX_DIGITS]; D];
+        self.render(&mut frame);
+
+        display.write_frame(&frame).await
+    }
+}
+
+///
+/// Level meter on seven segment digits. Every digit provides two steps,
+/// segments E and F light up first followed by segments B and C,
+/// filling the digits from left to right.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SegmentMeter {
+    /// The value shown by the meter
+    pub level: Level,
+    /// Digits showing the meter
+    pub window: DigitWindow,
+}
+
+impl SegmentMeter {
+    ///
+    /// Construct a new segment meter
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - the value shown by the meter
+    /// * `window` - digits showing the meter
+    ///
+    pub const fn new(level: Level, window: DigitWindow) -> Self {
+        SegmentMeter { level, window }
+    }
+
+    ///
+    /// Renders the meter into raw segment bytes, leftmost digit first.
+    /// Returns the number of digits rendered.
+    ///
+    pub fn render(&self, digits: &mut [u8; MAX_DIGITS]) -> usize {
+        let width = self.window.width();
+        let steps = width * 2;
+        *digits = [0x00; MAX_DIGITS];
+
+        let mut light = |step: usize| {
+            digits[step / 2] |= if step.is_multiple_of(2) {
+                SEGMENTS_LEFT
+            } else {
+                SEGMENTS_RIGHT
+            };
+        };
+
+        (0..self.level.fill(steps)).for_each(&mut light);
+        self.level.markers(steps, light);
+
+        width
+    }
+
+    ///
+    /// Renders and writes the meter to its digits
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let mut digits = [0x00; MAX_DIGITS];
+        let width = self.render(&mut digits);
+
+        self.window.write_segments(display, &digits[..width]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, recorder};
+
+    fn level(low: i32, high: i32, values: &[i32]) -> Level {
+        let mut level = Level::new(low, high);
+        for value in values {
+            level.update(*value);
+        }
+        level
+    }
+
+    #[test]
+    fn scales_clamped_values() {
+        let mut level = level(-50, 50, &[0]);
+        assert_eq!(level.fill(16), 8);
+
+        level.update(200);
+        assert_eq!((level.value(), level.fill(16)), (50, 16));
+        level.update(i32::MIN);
+        assert_eq!((level.value(), level.fill(16)), (-50, 0));
+    }
+
+    #[test]
+    fn holds_and_drops_peak() {
+        let mut level = Level::new(0, 100).with_peak_hold(2);
+
+        level.update(80);
+        level.update(20);
+        level.update(20);
+        assert_eq!(level.peak(), 80);
+        level.update(20);
+        assert_eq!(level.peak(), 20);
+        level.update(90);
+        assert_eq!(level.peak(), 90);
+    }
+
+    #[test]
+    fn tracks_min_max() {
+        let mut level = Level::new(0, 100).with_min_max();
+        assert_eq!((level.min(), level.max()), (None, None));
+
+        level.update(30);
+        level.update(70);
+        assert_eq!((level.min(), level.max()), (Some(30), Some(70)));
+
+        let mut marked = [false; 10];
+        level.markers(10, |step| marked[step] = true);
+        assert_eq!(marked.map(u8::from), [0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+
+        level.reset_min_max();
+        assert_eq!((level.min(), level.max()), (None, None));
+    }
+
+    #[test]
+    fn renders_horizontal_bar_with_peak() {
+        let mut level = Level::new(0, 16).with_peak_hold(10);
+        level.update(12);
+        level.update(5);
+        let bar = BarGraph::new(level, Orientation::Horizontal);
+
+        let mut frame = [[0xAA; MAX_DIGITS]; 2];
+        bar.render(&mut frame);
+
+        assert_eq!(
+            frame,
+            [[0b1111_1000; MAX_DIGITS], [0b0001_0000; MAX_DIGITS]]
+        );
+    }
+
+    #[test]
+    fn writes_vertical_bar() {
+        let mut display = recorder::<2>();
+        let bar = BarGraph::new(level(0, 8, &[3]), Orientation::Vertical);
+
+        block_on(bar.write(&mut display)).unwrap();
+
+        // registers hold digit 0 first, the bar grows from digit 7 upwards
+        let rows = [0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let digits: [u8; MAX_DIGITS] = core::array::from_fn(|i| rows[MAX_DIGITS - 1 - i]);
+        assert_eq!(display.registers[0].digits, digits);
+        assert_eq!(display.registers[1].digits, digits);
+    }
+
+    #[test]
+    fn renders_segment_meter_in_halves() {
+        let meter = SegmentMeter::new(level(0, 8, &[3]), DigitWindow::new(0, 4, 4));
+
+        let mut digits = [0xFF; MAX_DIGITS];
+        assert_eq!(meter.render(&mut digits), 4);
+        assert_eq!(
+            digits[..4],
+            [SEGMENTS_LEFT | SEGMENTS_RIGHT, SEGMENTS_LEFT, 0, 0]
+        );
+
+        let mut display = recorder::<1>();
+        block_on(meter.write(&mut display)).unwrap();
+        assert_eq!(
+            display.registers[0].digits[..4],
+            [0, 0, SEGMENTS_LEFT, SEGMENTS_LEFT | SEGMENTS_RIGHT]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Independent fields on a single seven segment display
+
+use crate::connectors::Connector;
+use crate::font::SegmentFont;
+use crate::{DataError, MAX_DIGITS, MAX7219, NumberFormat};
+
+///
+/// A window of consecutive digits on one display. Writes through a window
+/// only rewrite the digits it covers, so a single 8 digit display can show
+/// several independent values, e.g. a 4 digit setpoint next to a 4 digit reading.
+///
+/// Digit positions are counted from the left, position 0 being the leftmost digit.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DigitWindow {
+    addr: usize,
+    start: usize,
+    width: usize,
+}
+
+impl DigitWindow {
+    ///
+    /// Construct a new window
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - display to address as connected in series (0 -> last)
+    /// * `start` - leftmost digit of the window (0 -> leftmost)
+    /// * `width` - number of digits in the window
+    ///
+    /// # Panics
+    ///
+    /// * if the window does not fit into the 8 digits of a display
+    ///
+    pub const fn new(addr: usize, start: usize, width: usize) -> Self {
+        assert!(start + width <= MAX_DIGITS, "window exceeds display digits");
+
+        DigitWindow { addr, start, width }
+    }
+
+    /// Display addressed by this window
+    pub const fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Leftmost digit of this window
+    pub const fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Number of digits in this window
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    ///
+    /// Writes byte string into the window. Shorter strings are blanked
+    /// on the right, longer strings are cut off.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `string` - the byte string to write. Unknown characters result in question mark.
+    /// * `dots` - u8 bit array specifying where to put dots, highest bit is the leftmost window digit
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_str<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        string: &[u8],
+        dots: u8,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let mut buf = [b' '; MAX_DIGITS];
+        let len = string.len().min(self.width);
+        buf[..len].copy_from_slice(&string[..len]);
+
+        display
+            .write_ascii(self.addr, self.start, &buf[..self.width], dots)
+            .await
+    }
+
+    ///
+    /// Writes a formatted number into the window. The window width
+    /// overrides the width set in `format`.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `value` - the number to write
+    /// * `format` - describes radix, alignment, sign and overflow handling
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_number<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        value: i64,
+        format: &NumberFormat,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let format = NumberFormat {
+            width: self.width,
+            ..*format
+        };
+
+        display
+            .write_number(self.addr, self.start, value, &format)
+            .await
+    }
+
+    ///
+    /// Writes a right justified integer with sign into the window
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `value` - an integer i32
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_integer<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        value: i32,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        self.write_number(display, value.into(), &NumberFormat::decimal())
+            .await
+    }
+
+    ///
+    /// Writes a right justified hex formatted integer into the window
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `value` - an integer u32
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_hex<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        value: u32,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        self.write_number(display, value.into(), &NumberFormat::hex())
+            .await
+    }
+
+    ///
+    /// Writes raw segment bytes into the window, leftmost digit first.
+    /// Shorter input is blanked on the right, longer input is cut off.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `raw` - segment bytes to write, each bit represents a segment
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_segments<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        raw: &[u8],
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let mut mask = display.decode_mask(self.addr);
+        for i in self.start..self.start + self.width {
+            mask &= !(1 << (MAX_DIGITS - 1 - i));
+        }
+        display.set_decode_mask(self.addr, mask).await?;
+
+        let mut buf = [0x00; MAX_DIGITS];
+        let len = raw.len().min(self.width);
+        buf[..len].copy_from_slice(&raw[..len]);
+
+        let mut digit = (MAX_DIGITS - self.start) as u8;
+        for b in &buf[..self.width] {
+            display.write_raw_byte(self.addr, digit, *b).await?;
+            digit -= 1;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Writes text using a segment font. Characters missing from the font
+    /// are shown as `?` if the font has one, blank otherwise. Shorter text
+    /// is blanked on the right, longer text is cut off.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    /// * `font` - the segment font to look characters up in
+    /// * `text` - the text to write
+    /// * `dots` - u8 bit array specifying where to put dots, highest bit is the leftmost window digit
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn write_text<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+        font: &SegmentFont<'_>,
+        text: &str,
+        dots: u8,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        let mut raw = [0x00; MAX_DIGITS];
+        for (i, c) in text.chars().take(self.width).enumerate() {
+            raw[i] = font.segments(c).or(font.segments('?')).unwrap_or(0x00);
+        }
+        for (i, byte) in raw.iter_mut().take(self.width).enumerate() {
+            if dots & (0b1000_0000 >> i) != 0 {
+                *byte |= 0b1000_0000;
+            }
+        }
+
+        self.write_segments(display, &raw[..self.width]).await
+    }
+
+    ///
+    /// Blanks all digits of the window
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - the driver to write through
+    ///
+    /// # Errors
+    ///
+    /// * `DataError` - returned in case there was an error during data transfer
+    ///
+    pub async fn clear<const D: usize, CONNECTOR>(
+        &self,
+        display: &mut MAX7219<D, CONNECTOR>,
+    ) -> Result<(), DataError>
+    where
+        CONNECTOR: Connector,
+    {
+        self.write_segments(display, &[0x00; MAX_DIGITS]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssb_byte;
+    use crate::testing::{block_on, recorder, take};
+
+    extern crate std;
+    use std::vec::Vec;
+
+    const WINDOW: DigitWindow = DigitWindow::new(0, 2, 3);
+
+    // digit transfers as (position from the left, byte)
+    fn digits(transfers: &[Vec<u8>]) -> Vec<(usize, u8)> {
+        transfers
+            .iter()
+            .filter(|t| (0x01..=0x08).contains(&t[0]))
+            .map(|t| (MAX_DIGITS - t[0] as usize, t[1]))
+            .collect()
+    }
+
+    #[test]
+    fn blanks_rest_of_short_strings() {
+        let mut display = recorder::<1>();
+
+        block_on(WINDOW.write_str(&mut display, b"1", 0b0100_0000)).unwrap();
+
+        let blank_dot = ssb_byte(b' ', true);
+        assert_eq!(
+            digits(&take(&mut display)),
+            [(2, ssb_byte(b'1', false)), (3, blank_dot), (4, 0x00)]
+        );
+    }
+
+    #[test]
+    fn blanks_rest_of_short_segments() {
+        let mut display = recorder::<1>();
+
+        block_on(WINDOW.write_segments(&mut display, &[0x7F])).unwrap();
+        assert_eq!(
+            digits(&take(&mut display)),
+            [(2, 0x7F), (3, 0x00), (4, 0x00)]
+        );
+
+        block_on(WINDOW.write_segments(&mut display, &[0x01; MAX_DIGITS])).unwrap();
+        assert_eq!(
+            digits(&take(&mut display)),
+            [(2, 0x01), (3, 0x01), (4, 0x01)]
+        );
+    }
+
+    #[test]
+    fn keeps_decoding_outside_window() {
+        let mut display = recorder::<1>();
+        block_on(async {
+            display.write_digits(0, &[0x0F; MAX_DIGITS]).await?;
+            display.set_decode_mask(0, 0xFF).await
+        })
+        .unwrap();
+
+        block_on(WINDOW.clear(&mut display)).unwrap();
+
+        // registers hold digit 0 (the rightmost) first
+        assert_eq!(display.decode_mask(0), 0b1100_0111);
+        let rest = [0x0F, 0x0F, 0x0F, 0x00, 0x00, 0x00, 0x0F, 0x0F];
+        assert_eq!(display.registers[0].digits, rest);
+    }
+
+    #[test]
+    fn formats_to_window_width() {
+        let mut display = recorder::<1>();
+        let format = NumberFormat {
+            width: 8,
+            ..NumberFormat::decimal()
+        };
+
+        block_on(WINDOW.write_number(&mut display, 42, &format)).unwrap();
+
+        let expected = [
+            (2, ssb_byte(b' ', false)),
+            (3, ssb_byte(b'4', false)),
+            (4, ssb_byte(b'2', false)),
+        ];
+        assert_eq!(digits(&take(&mut display)), expected);
+    }
+}
@@ -0,0 +1,175 @@
+//! BDF (Glyph Bitmap Distribution Format) parsing
+
+use crate::Bitmap;
+
+///
+/// Parses all encoded glyphs of a BDF font into bitmaps aligned
+/// to the font bounding box, so all glyphs share one baseline.
+///
+pub fn parse(text: &str) -> Result<Vec<Bitmap>, String> {
+    let mut glyphs = Vec::new();
+    let mut font_box = None;
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("FONTBOUNDINGBOX") => {
+                let bounds = numbers::<4>(words, line)?;
+                if bounds[0] < 0 || bounds[1] < 0 {
+                    return Err(format!("negative bounding box: {line}"));
+                }
+                font_box = Some(bounds);
+            }
+            Some("STARTCHAR") => {
+                let [cell_w, cell_h, cell_x, cell_y] =
+                    font_box.ok_or("STARTCHAR before FONTBOUNDINGBOX")?;
+                if let Some(glyph) = parse_char(&mut lines, cell_w, cell_h, cell_x, cell_y)? {
+                    glyphs.push(glyph);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(glyphs)
+}
+
+fn parse_char<'a>(
+    lines: &mut impl Iterator<Item = &'a str>,
+    cell_w: i32,
+    cell_h: i32,
+    cell_x: i32,
+    cell_y: i32,
+) -> Result<Option<Bitmap>, String> {
+    let mut encoding = -1;
+    let mut advance = None;
+    let mut bbx = [cell_w, cell_h, cell_x, cell_y];
+
+    for line in lines.by_ref() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("ENCODING") => encoding = numbers::<1>(words, line)?[0],
+            Some("DWIDTH") => advance = Some(numbers::<1>(words, line)?[0]),
+            Some("BBX") => bbx = numbers::<4>(words, line)?,
+            Some("BITMAP") => break,
+            _ => {}
+        }
+    }
+
+    let [w, h, x_off, y_off] = bbx;
+    if w < 0 || h < 0 {
+        return Err(format!("negative glyph size {w}x{h}"));
+    }
+    let width = advance.unwrap_or(w + x_off.max(0)).max(0) as usize;
+    let mut bitmap = Bitmap::new(' ', width, cell_h as usize);
+
+    // top row of the glyph box counted from the top of the cell
+    let top = (cell_h + cell_y) - (y_off + h);
+    for row in 0..h {
+        let line = lines.next().ok_or("BITMAP ended early")?.trim();
+        let bits = u128::from_str_radix(line, 16).map_err(|e| format!("{line}: {e}"))?;
+        let row_bits = line.len() as i32 * 4;
+        if w > row_bits {
+            return Err(format!("{line}: narrower than BBX width {w}"));
+        }
+        for column in 0..w {
+            if bits & (1 << (row_bits - 1 - column)) != 0 {
+                bitmap.set(x_off + column, top + row, true);
+            }
+        }
+    }
+
+    if lines.next().map(str::trim) != Some("ENDCHAR") {
+        return Err("missing ENDCHAR".into());
+    }
+
+    // unencoded glyphs and invalid code points are skipped
+    Ok(u32::try_from(encoding)
+        .ok()
+        .and_then(char::from_u32)
+        .map(|c| Bitmap {
+            codepoint: c,
+            ..bitmap
+        }))
+}
+
+fn numbers<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+    line: &str,
+) -> Result<[i32; N], String> {
+    let mut result = [0; N];
+    for number in result.iter_mut() {
+        *number = words
+            .next()
+            .and_then(|w| w.parse().ok())
+            .ok_or_else(|| format!("malformed line: {line}"))?;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &str = "STARTFONT 2.1
+FONTBOUNDINGBOX 4 4 0 -1
+CHARS 2
+STARTCHAR A
+ENCODING 65
+DWIDTH 4 0
+BBX 3 3 0 0
+BITMAP
+40
+A0
+E0
+ENDCHAR
+STARTCHAR unencoded
+ENCODING -1
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+ENDFONT
+";
+
+    fn rows(bitmap: &Bitmap) -> Vec<String> {
+        (0..bitmap.height)
+            .map(|y| {
+                (0..bitmap.width)
+                    .map(|x| if bitmap.pixel(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_glyphs_on_baseline() {
+        let glyphs = parse(FONT).unwrap();
+
+        assert_eq!(glyphs.len(), 1);
+        assert_eq!(glyphs[0].codepoint, 'A');
+        assert_eq!(rows(&glyphs[0]), [".#..", "#.#.", "###.", "...."]);
+    }
+
+    #[test]
+    fn rejects_truncated_bitmap() {
+        let truncated = FONT.replace("E0\nENDCHAR", "E0\n");
+
+        assert!(parse(&truncated).is_err());
+        assert!(parse("STARTCHAR A\n").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_sizes() {
+        let wide = FONT.replace("BBX 3 3 0 0", "BBX 9 3 0 0");
+        assert!(parse(&wide).is_err());
+
+        let negative = FONT.replace("BBX 3 3 0 0", "BBX 3 -3 0 0");
+        assert!(parse(&negative).is_err());
+
+        let negative = FONT.replace("FONTBOUNDINGBOX 4 4", "FONTBOUNDINGBOX 4 -4");
+        assert!(parse(&negative).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Converts BDF and PSF bitmap fonts into `max7219::font` tables
+//!
+//! ```text
+//! max7219-fontgen [--segment] [--name NAME] [--range FIRST-LAST]... [--top ROW] <FONT>
+//! ```
+//!
+//! The generated Rust source is written to standard output. Matrix fonts keep
+//! 8 rows starting at `--top` and glyphs up to 8 columns wide, blank columns
+//! on both sides are trimmed. Segment fonts map each glyph onto the seven
+//! segments by sampling the regions each segment covers.
+
+mod bdf;
+mod psf;
+
+use std::fmt::Write as _;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: max7219-fontgen [--segment] [--name NAME] \
+                     [--range FIRST-LAST]... [--top ROW] <FONT>";
+
+/// Rows and columns of a matrix glyph
+const CELL: usize = 8;
+
+///
+/// A decoded glyph of any size, row by row
+///
+#[derive(Clone, Debug)]
+pub struct Bitmap {
+    pub codepoint: char,
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn new(codepoint: char, width: usize, height: usize) -> Self {
+        Bitmap {
+            codepoint,
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    /// Sets a pixel, pixels outside the bitmap are ignored
+    pub fn set(&mut self, x: i32, y: i32, on: bool) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.pixels[y as usize * self.width + x as usize] = on;
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.pixels[y * self.width + x]
+    }
+}
+
+struct Options {
+    segment: bool,
+    name: String,
+    ranges: Vec<(u32, u32)>,
+    top: usize,
+    path: String,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(source) => {
+            print!("{source}");
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("max7219-fontgen: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<String, String> {
+    let options = parse_args(std::env::args().skip(1))?;
+    let bytes = std::fs::read(&options.path).map_err(|e| format!("{}: {e}", options.path))?;
+
+    let mut glyphs = if bytes.starts_with(b"STARTFONT") {
+        let text = String::from_utf8_lossy(&bytes);
+        bdf::parse(&text)?
+    } else {
+        psf::parse(&bytes)?
+    };
+
+    glyphs.retain(|glyph| {
+        let c = glyph.codepoint as u32;
+        options
+            .ranges
+            .iter()
+            .any(|(first, last)| (*first..=*last).contains(&c))
+    });
+    // tables must be sorted without duplicates, the first glyph for a code point wins
+    glyphs.sort_by_key(|glyph| glyph.codepoint);
+    glyphs.dedup_by_key(|glyph| glyph.codepoint);
+
+    if glyphs.is_empty() {
+        return Err("no glyphs in the selected ranges".into());
+    }
+
+    Ok(if options.segment {
+        segment_font(&options, &glyphs)
+    } else {
+        matrix_font(&options, &glyphs)
+    })
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut segment = false;
+    let mut name = None;
+    let mut ranges = Vec::new();
+    let mut top = 0;
+    let mut path = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--segment" => segment = true,
+            "--name" => name = Some(value()?),
+            "--range" => ranges.push(parse_range(&value()?)?),
+            "--top" => top = value()?.parse().map_err(|_| "invalid --top")?,
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}\n{USAGE}")),
+            _ => path = Some(arg),
+        }
+    }
+
+    if ranges.is_empty() {
+        ranges.push((0x20, 0x7E));
+    }
+
+    Ok(Options {
+        segment,
+        name: name.unwrap_or_else(|| if segment { "SEGMENT_FONT" } else { "FONT" }.into()),
+        ranges,
+        top,
+        path: path.ok_or(USAGE)?,
+    })
+}
+
+// parses `FIRST-LAST` or a single code point, each decimal or `0x` prefixed hex
+fn parse_range(range: &str) -> Result<(u32, u32), String> {
+    let number = |s: &str| {
+        let s = s.trim();
+        match s.strip_prefix("0x").or_else(|| s.strip_prefix("U+")) {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => s.parse(),
+        }
+        .map_err(|_| format!("invalid range {range}"))
+    };
+
+    match range.split_once('-') {
+        Some((first, last)) => Ok((number(first)?, number(last)?)),
+        None => number(range).map(|c| (c, c)),
+    }
+}
+
+fn matrix_font(options: &Options, glyphs: &[Bitmap]) -> String {
+    let mut source = format!(
+        "pub const {}: max7219::font::MatrixFont<'static> = max7219::font::MatrixFont::new(&[\n",
+        options.name
+    );
+
+    for glyph in glyphs {
+        let mut columns: Vec<u8> = (0..glyph.width)
+            .map(|x| {
+                (0..CELL)
+                    .filter(|row| glyph.pixel(x, options.top + row))
+                    .fold(0, |column, row| column | 1 << row)
+            })
+            .collect();
+
+        // keep the advance of blank glyphs such as space
+        let first = columns.iter().position(|c| *c != 0);
+        let last = columns.iter().rposition(|c| *c != 0);
+        columns = match (first, last) {
+            (Some(first), Some(last)) => columns[first..=last].to_vec(),
+            _ => vec![0; glyph.width.min(CELL)],
+        };
+        if columns.len() > CELL {
+            eprintln!(
+                "max7219-fontgen: {:?} is {} columns wide, cut to {CELL}",
+                glyph.codepoint,
+                columns.len()
+            );
+        }
+        columns.resize(columns.len().min(CELL), 0);
+        let width = columns.len();
+        columns.resize(CELL, 0);
+
+        let columns = columns
+            .iter()
+            .map(|c| format!("0x{c:02X}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(
+            source,
+            "    max7219::font::Glyph::new({:?}, {width}, [{columns}]),",
+            glyph.codepoint
+        );
+    }
+
+    source.push_str("]);\n");
+    source
+}
+
+fn segment_font(options: &Options, glyphs: &[Bitmap]) -> String {
+    let mut source = format!(
+        "pub const {}: max7219::font::SegmentFont<'static> = max7219::font::SegmentFont::new(&[\n",
+        options.name
+    );
+
+    for glyph in glyphs {
+        let _ = writeln!(
+            source,
+            "    ({:?}, 0b{:08b}),",
+            glyph.codepoint,
+            segments(glyph)
+        );
+    }
+
+    source.push_str("]);\n");
+    source
+}
+
+///
+/// Maps a glyph onto the segments, bit 6 is segment A down to bit 0 for G.
+/// A segment is lit if enough pixels of the region it covers are lit.
+///
+fn segments(glyph: &Bitmap) -> u8 {
+    let lit: Vec<(usize, usize)> = (0..glyph.height)
+        .flat_map(|y| (0..glyph.width).map(move |x| (x, y)))
+        .filter(|(x, y)| glyph.pixel(*x, *y))
+        .collect();
+    let Some(top) = lit.iter().map(|(_, y)| *y).min() else {
+        return 0;
+    };
+    let bottom = lit.iter().map(|(_, y)| *y).max().unwrap_or(top);
+    let left = lit.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let right = lit.iter().map(|(x, _)| *x).max().unwrap_or(left);
+
+    // regions relative to the inked box, in fractions of its width and height
+    let (w, h) = ((right - left + 1) as f32, (bottom - top + 1) as f32);
+    let region = |x0: f32, x1: f32, y0: f32, y1: f32| {
+        let inside = |(x, y): &&(usize, usize)| {
+            let fx = (*x - left) as f32 / w;
+            let fy = (*y - top) as f32 / h;
+            (x0..x1).contains(&fx) && (y0..y1).contains(&fy)
+        };
+        let area = ((x1 - x0) * w * (y1 - y0) * h).max(1.0);
+        lit.iter().filter(inside).count() as f32 / area >= 0.25
+    };
+
+    // too small to have distinct segments, a dot or dash
+    if h < 3.0 {
+        return if w >= 2.0 { 0b0000_0001 } else { 0 };
+    }
+
+    let regions = [
+        region(0.25, 0.75, 0.0, 0.2), // A
+        region(0.6, 1.0, 0.15, 0.45), // B
+        region(0.6, 1.0, 0.55, 0.85), // C
+        region(0.25, 0.75, 0.8, 1.0), // D
+        region(0.0, 0.4, 0.55, 0.85), // E
+        region(0.0, 0.4, 0.15, 0.45), // F
+        region(0.25, 0.75, 0.4, 0.6), // G
+    ];
+
+    regions
+        .iter()
+        .fold(0, |segments, lit| segments << 1 | *lit as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(codepoint: char, rows: &[&str]) -> Bitmap {
+        let mut bitmap = Bitmap::new(codepoint, rows[0].len(), rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, pixel) in row.chars().enumerate() {
+                bitmap.set(x as i32, y as i32, pixel == '#');
+            }
+        }
+        bitmap
+    }
+
+    fn options(top: usize) -> Options {
+        Options {
+            segment: false,
+            name: "TEST".into(),
+            ranges: Vec::new(),
+            top,
+            path: String::new(),
+        }
+    }
+
+    #[test]
+    fn samples_segments() {
+        let eight = glyph(
+            '8',
+            &[
+                "#####", "#...#", "#...#", "#####", "#...#", "#...#", "#####",
+            ],
+        );
+        let seven = glyph(
+            '7',
+            &[
+                "#####", "....#", "....#", "....#", "....#", "....#", "....#",
+            ],
+        );
+        let dash = glyph('-', &[".....", ".###.", "....."]);
+        let blank = glyph(' ', &["...", "..."]);
+
+        assert_eq!(segments(&eight), 0b0111_1111);
+        assert_eq!(segments(&seven), 0b0111_0000);
+        assert_eq!(segments(&dash), 0b0000_0001);
+        assert_eq!(segments(&blank), 0);
+    }
+
+    #[test]
+    fn builds_matrix_glyphs() {
+        let glyphs = [
+            glyph(' ', &["....", "....", "...."]),
+            glyph(
+                'i',
+                &[
+                    "########", "...#....", "........", "...#....", "...#....", "...#....",
+                    "........", "........", "........",
+                ],
+            ),
+            glyph('W', &["##########"; 9]),
+        ];
+
+        let source = matrix_font(&options(1), &glyphs);
+        let lines: Vec<&str> = source.lines().collect();
+
+        assert_eq!(
+            lines,
+            [
+                "pub const TEST: max7219::font::MatrixFont<'static> = max7219::font::MatrixFont::new(&[",
+                "    max7219::font::Glyph::new(' ', 4, [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),",
+                "    max7219::font::Glyph::new('i', 1, [0x1D, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),",
+                "    max7219::font::Glyph::new('W', 8, [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]),",
+                "]);",
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! PSF (PC Screen Font) version 1 and 2 parsing
+
+use crate::Bitmap;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_UNICODE: u8 = 0x06;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_FLAG_UNICODE: u32 = 0x01;
+
+///
+/// Parses all glyphs of a PSF font. Fonts with a unicode table get one
+/// bitmap per mapped code point, others map glyph indices to code points.
+///
+pub fn parse(bytes: &[u8]) -> Result<Vec<Bitmap>, String> {
+    if bytes.starts_with(&PSF1_MAGIC) {
+        parse_psf1(bytes)
+    } else if bytes.starts_with(&PSF2_MAGIC) {
+        parse_psf2(bytes)
+    } else {
+        Err("not a PSF font".into())
+    }
+}
+
+fn parse_psf1(bytes: &[u8]) -> Result<Vec<Bitmap>, String> {
+    let header = bytes.get(..4).ok_or("truncated header")?;
+    let mode = header[2];
+    let height = header[3] as usize;
+    let count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+
+    let glyphs = &bytes[4..];
+    let table = glyphs.get(count * height..).ok_or("truncated glyphs")?;
+
+    let mut codepoints = vec![Vec::new(); count];
+    if mode & PSF1_MODE_UNICODE != 0 {
+        let mut entries = table
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+        for mapped in codepoints.iter_mut() {
+            let mut in_sequence = false;
+            for entry in entries.by_ref() {
+                match entry {
+                    0xFFFF => break,
+                    0xFFFE => in_sequence = true,
+                    c if !in_sequence => mapped.extend(char::from_u32(c.into())),
+                    _ => {}
+                }
+            }
+        }
+    } else {
+        for (index, mapped) in codepoints.iter_mut().enumerate() {
+            mapped.extend(char::from_u32(index as u32));
+        }
+    }
+
+    Ok(bitmaps(glyphs, &codepoints, 8, height))
+}
+
+fn parse_psf2(bytes: &[u8]) -> Result<Vec<Bitmap>, String> {
+    let field = |index: usize| -> Result<usize, String> {
+        let b = bytes
+            .get(4 + index * 4..8 + index * 4)
+            .ok_or("truncated header")?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+    let header_size = field(1)?;
+    let flags = field(2)? as u32;
+    let count = field(3)?;
+    let glyph_size = field(4)?;
+    let height = field(5)?;
+    let width = field(6)?;
+
+    // glyph rows are padded to whole bytes, glyphs may be padded further
+    let stride = width.div_ceil(8);
+    stride
+        .checked_mul(height)
+        .filter(|size| (1..=glyph_size).contains(size))
+        .ok_or_else(|| format!("glyph size {glyph_size} does not fit {width}x{height}"))?;
+
+    let glyphs = bytes.get(header_size..).ok_or("truncated header")?;
+    let glyphs_size = count.checked_mul(glyph_size).ok_or("truncated glyphs")?;
+    let table = glyphs.get(glyphs_size..).ok_or("truncated glyphs")?;
+
+    let mut codepoints = vec![Vec::new(); count];
+    if flags & PSF2_FLAG_UNICODE != 0 {
+        let mut entries = table.split(|b| *b == 0xFF);
+        for mapped in codepoints.iter_mut() {
+            let entry = entries.next().unwrap_or_default();
+            // sequences of combining characters follow 0xFE and are skipped
+            let single = entry.split(|b| *b == 0xFE).next().unwrap_or_default();
+            mapped.extend(String::from_utf8_lossy(single).chars());
+        }
+    } else {
+        for (index, mapped) in codepoints.iter_mut().enumerate() {
+            mapped.extend(char::from_u32(index as u32));
+        }
+    }
+
+    let mut result = Vec::new();
+    for (index, mapped) in codepoints.iter().enumerate() {
+        let glyph = &glyphs[index * glyph_size..];
+        for c in mapped {
+            let mut bitmap = Bitmap::new(*c, width, height);
+            for y in 0..height {
+                for x in 0..width {
+                    if glyph[y * stride + x / 8] & (0x80 >> (x % 8)) != 0 {
+                        bitmap.set(x as i32, y as i32, true);
+                    }
+                }
+            }
+            result.push(bitmap);
+        }
+    }
+
+    Ok(result)
+}
+
+// builds bitmaps of 8 pixel wide glyphs stored one byte per row
+fn bitmaps(glyphs: &[u8], codepoints: &[Vec<char>], width: usize, height: usize) -> Vec<Bitmap> {
+    let mut result = Vec::new();
+    for (index, mapped) in codepoints.iter().enumerate() {
+        for c in mapped {
+            let mut bitmap = Bitmap::new(*c, width, height);
+            for y in 0..height {
+                let row = glyphs[index * height + y];
+                for x in 0..width {
+                    if row & (0x80 >> x) != 0 {
+                        bitmap.set(x as i32, y as i32, true);
+                    }
+                }
+            }
+            result.push(bitmap);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn psf1(unicode: bool) -> Vec<u8> {
+        let mode = if unicode { PSF1_MODE_UNICODE } else { 0 };
+        let mut bytes = vec![PSF1_MAGIC[0], PSF1_MAGIC[1], mode, 2];
+        bytes.extend((0..256).flat_map(|index| [index as u8, 0x80]));
+        if unicode {
+            for index in 0..256u16 {
+                bytes.extend(index.to_le_bytes());
+                if index == 0x41 {
+                    bytes.extend(0x391u16.to_le_bytes());
+                }
+                bytes.extend(0xFFFFu16.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_psf1() {
+        let glyphs = parse(&psf1(false)).unwrap();
+
+        assert_eq!(glyphs.len(), 256);
+        let glyph = &glyphs[0x41];
+        assert_eq!(glyph.codepoint, 'A');
+        assert_eq!((glyph.width, glyph.height), (8, 2));
+        let top: Vec<bool> = (0..8).map(|x| glyph.pixel(x, 0)).collect();
+        assert_eq!(top, [false, true, false, false, false, false, false, true]);
+        assert!(glyph.pixel(0, 1) && !glyph.pixel(1, 1));
+    }
+
+    #[test]
+    fn maps_psf1_unicode_table() {
+        let glyphs = parse(&psf1(true)).unwrap();
+
+        assert_eq!(glyphs.len(), 257);
+        let alpha = glyphs.iter().find(|glyph| glyph.codepoint == 'Α').unwrap();
+        assert_eq!(alpha.pixels, glyphs[0x41].pixels);
+    }
+
+    // version, header size, flags, count, glyph size, height, width
+    fn psf2(fields: [u32; 7]) -> Vec<u8> {
+        let mut bytes = PSF2_MAGIC.to_vec();
+        for field in fields {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_psf2() {
+        let mut bytes = psf2([0, 32, PSF2_FLAG_UNICODE, 1, 4, 2, 10]);
+        bytes.extend([0xC0, 0x40, 0x00, 0x80]);
+        bytes.extend("x".as_bytes());
+        bytes.push(0xFE);
+        bytes.extend("x\u{301}".as_bytes());
+        bytes.push(0xFF);
+
+        let glyphs = parse(&bytes).unwrap();
+
+        assert_eq!(glyphs.len(), 1);
+        let glyph = &glyphs[0];
+        assert_eq!(glyph.codepoint, 'x');
+        assert_eq!((glyph.width, glyph.height), (10, 2));
+        let lit: Vec<(usize, usize)> = (0..2)
+            .flat_map(|y| (0..10).map(move |x| (x, y)))
+            .filter(|(x, y)| glyph.pixel(*x, *y))
+            .collect();
+        assert_eq!(lit, [(0, 0), (1, 0), (9, 0), (8, 1)]);
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        assert!(parse(b"BM").is_err());
+        assert!(parse(&psf1(false)[..100]).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_psf2() {
+        let glyphs = [0xFF; 64];
+
+        // glyph size smaller than the rows it holds
+        let mut small = psf2([0, 32, 0, 2, 2, 2, 10]);
+        small.extend(glyphs);
+        assert!(parse(&small).is_err());
+
+        let mut empty = psf2([0, 32, 0, u32::MAX, 0, 0, 0]);
+        empty.extend(glyphs);
+        assert!(parse(&empty).is_err());
+
+        // count times glyph size overflowing or past the end
+        let mut huge = psf2([0, 32, 0, u32::MAX, u32::MAX, 8, 8]);
+        huge.extend(glyphs);
+        assert!(parse(&huge).is_err());
+    }
+}